@@ -0,0 +1,88 @@
+/// Command line tool for operating a running simple chat server
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use futures::{SinkExt, TryStreamExt};
+use simplechat_protocol::{AdminRequest, AdminRequestCodec, AdminResponse, AdminResponseCodec};
+use std::path::PathBuf;
+use tokio::net::UnixStream;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+#[derive(Debug, Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Admin socket of the server
+    #[arg(short, long, default_value = "simplechat-admin.sock")]
+    socket: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List connected clients
+    List,
+    /// Disconnect a user
+    Kick {
+        nick: String,
+        #[arg(default_value = "")]
+        reason: String,
+    },
+    /// Send a server notice
+    Notice {
+        /// Only send to this room instead of every room
+        #[arg(short, long, default_value = "")]
+        room: String,
+        text: String,
+    },
+    /// Re-read configuration from disk
+    Reload,
+    /// Show server statistics
+    Stats,
+}
+
+impl From<Command> for AdminRequest {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::List => AdminRequest::List,
+            Command::Kick { nick, reason } => AdminRequest::kick(nick, reason),
+            Command::Notice { room, text } => AdminRequest::notice(room, text),
+            Command::Reload => AdminRequest::Reload,
+            Command::Stats => AdminRequest::Stats,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let (rx, tx) = UnixStream::connect(&args.socket).await?.into_split();
+    let mut reader = FramedRead::new(rx, AdminResponseCodec::default());
+    let mut writer = FramedWrite::new(tx, AdminRequestCodec::default());
+    let listing = matches!(args.command, Command::List);
+    writer.send(args.command.into()).await?;
+
+    if listing {
+        println!(
+            "{:>6}  {:<22}  {:<20}  {:<16}  {:>6}",
+            "ID", "ADDRESS", "NICK", "ROOM", "IDLE"
+        );
+    }
+    while let Some(response) = reader.try_next().await? {
+        match response {
+            AdminResponse::Client(info) => println!(
+                "{:>6}  {:<22}  {:<20}  {:<16}  {:>5}s",
+                info.id, info.addr, info.nick, info.room, info.idle_secs
+            ),
+            AdminResponse::Stat { name, value } => println!("{}: {}", name, value),
+            AdminResponse::Ok(message) => {
+                if !message.is_empty() {
+                    println!("{}", message);
+                }
+                return Ok(());
+            }
+            AdminResponse::Error(message) => return Err(anyhow!(message)),
+        }
+    }
+    Err(anyhow!("server closed the connection"))
+}
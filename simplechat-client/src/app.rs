@@ -1,29 +1,79 @@
 /// Main simple chat client app
 use crate::{
+    commands,
     components::{
-        chat_history::ChatHistory,
-        text_input::{TextInput, TextInputAction},
+        chat_history::{ChatHistory, PendingMessage, Scroll},
+        text_input::{Mode, TextInput, TextInputAction},
+        typing::TypingIndicator,
     },
+    notify::Notify,
+    timestamps::Timestamps,
     tui::{Event, Tui},
 };
 use anyhow::Result;
-use crossterm::event::{KeyCode, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEventKind};
 use futures::{SinkExt, StreamExt};
 use ratatui::prelude::{Constraint, Direction, Layout};
 use simplechat_protocol::{
-    ClientFrame, ClientFrameCodec, SentMessage, ServerFrame, ServerFrameCodec,
+    mentions, same_nick, ClientFrame, ClientFrameCodec, MessageId, SentMessage, ServerFrame,
+    ServerFrameCodec,
 };
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::{
     io::{ReadHalf, WriteHalf},
-    net::{TcpStream, ToSocketAddrs},
+    net::TcpStream,
 };
 use tokio_util::codec::{FramedRead, FramedWrite};
 
+/// How often to remind others that the user is still typing
+const TYPING_REFRESH: Duration = Duration::from_secs(3);
+
+/// How long after the last keystroke the user stops counting as typing
+const TYPING_IDLE: Duration = Duration::from_secs(5);
+
+/// Entries scrolled by each step of the mouse wheel
+const WHEEL_STEP: usize = 1;
+
+/// How long to wait between attempts to reconnect after losing the server
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+
+/// How long one attempt to reconnect may hold up the UI
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Reader = FramedRead<ReadHalf<TcpStream>, ServerFrameCodec>;
+type Writer = FramedWrite<WriteHalf<TcpStream>, ClientFrameCodec>;
+
 /// Actions taken in response to events
 #[derive(Debug)]
 pub(crate) enum Action {
     Input(TextInputAction),
     Send,
+    /// Starts editing the user's last message
+    EditLast,
+    /// Moves the selection in history to an older message
+    SelectPrevious,
+    /// Moves the selection in history to a newer message
+    SelectNext,
+    /// Opens the thread of the selected message beside the history
+    OpenThread,
+    /// Selects the next older message mentioning the user
+    JumpToMention,
+    /// The terminal gained or lost focus
+    Focus(bool),
+    /// Moves through older or newer history
+    Scroll(Scroll),
+    /// Opens the box for searching history, from Vim's normal mode
+    StartSearch,
+    /// Selects the nearest older message matching the search box
+    Search,
+    /// Selects the next older, or newer, message matching the last search
+    SearchAgain {
+        older: bool,
+    },
+    /// Stops editing or replying, or closes the thread
+    Cancel,
+    /// Time passing, to expire anything shown only for a while
+    Tick,
     Quit,
 }
 
@@ -31,93 +81,656 @@ pub(crate) enum Action {
 /// actions, adjusts state, and then renders that state
 #[derive(Debug)]
 pub(crate) struct App<'a> {
+    connected: bool,
+    /// Whether the server ended the connection on purpose, so reconnecting
+    /// would only be refused again
+    refused: bool,
+    last_connect: Instant,
+    /// The message being edited, if any
+    editing: Option<MessageId>,
     history: ChatHistory<'a>,
     input: TextInput,
+    /// What to search history for, while it is being typed
+    search: Option<TextInput>,
+    last_search: Option<String>,
     quit: bool,
-    reader: FramedRead<ReadHalf<TcpStream>, ServerFrameCodec>,
-    writer: FramedWrite<WriteHalf<TcpStream>, ClientFrameCodec>,
+    /// When others were last told the user is typing, if they are
+    typing_sent: Option<Instant>,
+    last_keystroke: Instant,
+    typing: TypingIndicator,
+    /// The last message the server was told the user has seen
+    seen_sent: Option<MessageId>,
+    /// Whether the terminal has focus, assumed until it says otherwise
+    focused: bool,
+    notify: Notify,
+    reader: Reader,
+    writer: Writer,
+    /// Makes nonces unique across runs of the client, as the server remembers
+    /// them with the messages it stores
+    nonce_prefix: String,
+    nonces_used: u64,
+    addr: String,
     user: String,
+    room: String,
+    password: Option<String>,
 }
 
 impl<'a> App<'a> {
-    pub async fn connect(addr: impl ToSocketAddrs, user: impl Into<String>) -> Result<App<'a>> {
-        let (rx, tx) = tokio::io::split(TcpStream::connect(addr).await?);
-        let reader = FramedRead::new(rx, ServerFrameCodec::default());
-        let writer = FramedWrite::new(tx, ClientFrameCodec::default());
+    pub async fn connect(
+        addr: String,
+        user: String,
+        room: String,
+        password: Option<String>,
+        notify: Notify,
+        timestamps: Timestamps,
+        vim: bool,
+    ) -> Result<App<'a>> {
+        let (reader, writer) = open(&addr, &user, &room, password.as_deref()).await?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?;
         Ok(Self {
-            history: ChatHistory::default(),
-            input: TextInput::default(),
+            connected: true,
+            refused: false,
+            last_connect: Instant::now(),
+            editing: None,
+            history: ChatHistory::new(&user, timestamps),
+            input: if vim {
+                TextInput::default().with_modes()
+            } else {
+                TextInput::default()
+            },
+            search: None,
+            last_search: None,
             quit: false,
+            typing_sent: None,
+            last_keystroke: Instant::now(),
+            typing: TypingIndicator::default(),
+            seen_sent: None,
+            focused: true,
+            notify,
             reader,
             writer,
-            user: user.into(),
+            nonce_prefix: format!("{:x}", started.as_micros()),
+            nonces_used: 0,
+            addr,
+            user,
+            room,
+            password,
         })
     }
 
+    // Tries to get back in touch with the server, then sends the messages
+    // that were waiting for it
+    async fn reconnect(&mut self) -> Result<()> {
+        self.last_connect = Instant::now();
+        let open = open(&self.addr, &self.user, &self.room, self.password.as_deref());
+        let Ok(Ok((reader, writer))) = tokio::time::timeout(CONNECT_TIMEOUT, open).await else {
+            return Ok(());
+        };
+        self.reader = reader;
+        self.writer = writer;
+        self.connected = true;
+        self.history.push_notice("reconnected to server");
+        for message in self.history.pending() {
+            self.send_message(message).await;
+        }
+        Ok(())
+    }
+
+    // Notes that the connection has closed, and whether it is worth
+    // reconnecting
+    fn disconnected(&mut self) {
+        if !self.connected {
+            return;
+        }
+        self.connected = false;
+        self.typing_sent = None;
+        if self.refused {
+            self.history.push_error("disconnected from server");
+        } else {
+            self.history
+                .push_error("lost connection to server, reconnecting…");
+        }
+    }
+
+    // Sends `frame` if connected, treating a failure as the connection having
+    // gone. Returns whether it was sent.
+    async fn send(&mut self, frame: ClientFrame) -> bool {
+        if !self.connected {
+            return false;
+        }
+        let sent = self.writer.send(frame).await.is_ok();
+        if !sent {
+            self.disconnected();
+        }
+        sent
+    }
+
+    // Sends one of the user's messages, leaving it pending to be sent again
+    // after reconnecting if the connection has gone
+    async fn send_message(&mut self, message: PendingMessage) {
+        let mut sent = SentMessage::new(&self.user, message.text).with_nonce(message.nonce);
+        sent.reply_to = message.reply_to;
+        sent.thread = message.thread;
+        self.send(ClientFrame::send(sent)).await;
+    }
+
+    fn next_nonce(&mut self) -> String {
+        self.nonces_used += 1;
+        format!("{}-{}", self.nonce_prefix, self.nonces_used)
+    }
+
     async fn update(&mut self, action: Action) -> Result<Option<Action>> {
         match action {
             Action::Input(action) => self.do_input(action).await,
             Action::Send => self.do_send().await,
+            Action::EditLast => self.do_edit_last().await,
+            Action::SelectPrevious => self.do_select(ChatHistory::select_previous).await,
+            Action::SelectNext => self.do_select(ChatHistory::select_next).await,
+            Action::OpenThread => self.do_open_thread().await,
+            Action::JumpToMention => self.do_select(ChatHistory::select_mention).await,
+            Action::Focus(focused) => self.do_focus(focused).await,
+            Action::Scroll(scroll) => self.do_scroll(scroll).await,
+            Action::StartSearch => self.do_start_search().await,
+            Action::Search => self.do_search().await,
+            Action::SearchAgain { older } => self.do_search_again(older).await,
+            Action::Cancel => self.do_cancel().await,
+            Action::Tick => self.do_tick().await,
             Action::Quit => self.do_quit().await,
         }
     }
 
     async fn do_input(&mut self, action: TextInputAction) -> Result<Option<Action>> {
+        if let Some(search) = &mut self.search {
+            search.action(action);
+            return Ok(None);
+        }
+        let before = self.input.get_input();
         self.input.action(action);
+        if self.input.get_input() != before {
+            self.note_typing().await?;
+        }
+        Ok(None)
+    }
+
+    async fn do_tick(&mut self) -> Result<Option<Action>> {
+        self.typing.expire();
+        if !self.connected && !self.refused && self.last_connect.elapsed() > RECONNECT_DELAY {
+            self.reconnect().await?;
+        }
+        if self.last_keystroke.elapsed() > TYPING_IDLE {
+            self.stop_typing().await?;
+        }
+        self.report_seen().await?;
+        Ok(None)
+    }
+
+    // Tells the server how far the user has read, once anything new comes
+    // into view while they are looking
+    async fn report_seen(&mut self) -> Result<()> {
+        if !self.focused {
+            return Ok(());
+        }
+        let latest = self.history.latest_shown();
+        if latest > self.seen_sent {
+            if let Some(id) = latest {
+                if self.send(ClientFrame::seen(id)).await {
+                    self.seen_sent = latest;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Tells others the user is typing, without repeating it on every key
+    async fn note_typing(&mut self) -> Result<()> {
+        let input = self.input.get_input();
+        if input.is_empty() || input.starts_with('/') {
+            return self.stop_typing().await;
+        }
+        self.last_keystroke = Instant::now();
+        let due = self
+            .typing_sent
+            .map_or(true, |sent| sent.elapsed() > TYPING_REFRESH);
+        if due && self.send(ClientFrame::typing(true)).await {
+            self.typing_sent = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    async fn stop_typing(&mut self) -> Result<()> {
+        if self.typing_sent.take().is_some() {
+            self.send(ClientFrame::typing(false)).await;
+        }
+        Ok(())
+    }
+
+    async fn do_focus(&mut self, focused: bool) -> Result<Option<Action>> {
+        self.focused = focused;
         Ok(None)
     }
 
+    async fn do_scroll(&mut self, scroll: Scroll) -> Result<Option<Action>> {
+        self.history.scroll(scroll);
+        Ok(None)
+    }
+
+    // Gets the user's attention if they are looking elsewhere
+    fn notify(&self, title: &str, body: &str) {
+        if !self.focused {
+            // Not being able to write to the terminal will show soon enough
+            let _ = self.notify.send(title, body);
+        }
+    }
+
+    async fn do_edit_last(&mut self) -> Result<Option<Action>> {
+        let Some((id, text)) = self.history.last_own() else {
+            return Ok(None);
+        };
+        self.history.clear_selection();
+        self.editing = Some(id);
+        self.update_title();
+        Ok(Some(Action::Input(TextInputAction::Replace(text))))
+    }
+
+    async fn do_select(&mut self, select: fn(&mut ChatHistory<'a>)) -> Result<Option<Action>> {
+        if self.editing.is_none() {
+            select(&mut self.history);
+            self.update_title();
+        }
+        Ok(None)
+    }
+
+    async fn do_open_thread(&mut self) -> Result<Option<Action>> {
+        let Some(id) = self.history.selected() else {
+            return Ok(None);
+        };
+        self.history.clear_selection();
+        self.history.open_thread(id);
+        if let Some(root) = self.history.open_thread_root() {
+            // Fetches replies from before this client joined
+            self.send(ClientFrame::thread(root)).await;
+        }
+        self.update_title();
+        Ok(None)
+    }
+
+    async fn do_start_search(&mut self) -> Result<Option<Action>> {
+        let mut search = TextInput::default();
+        search.set_title(Some(String::from("Search (Enter to find, Esc to cancel)")));
+        self.search = Some(search);
+        Ok(None)
+    }
+
+    async fn do_search(&mut self) -> Result<Option<Action>> {
+        let Some(search) = self.search.take() else {
+            return Ok(None);
+        };
+        // As in Vim, searching for nothing repeats the last search
+        let query = search.get_input();
+        if !query.is_empty() {
+            self.last_search = Some(query);
+        }
+        self.do_search_again(true).await
+    }
+
+    async fn do_search_again(&mut self, older: bool) -> Result<Option<Action>> {
+        let Some(query) = &self.last_search else {
+            return Ok(None);
+        };
+        if self.editing.is_none() {
+            if !self.history.search(query, older) {
+                self.history
+                    .push_error(format!("No messages match \"{}\"", query));
+            }
+            self.update_title();
+        }
+        Ok(None)
+    }
+
+    async fn do_cancel(&mut self) -> Result<Option<Action>> {
+        let mut next = None;
+        if self.search.take().is_some() {
+            // Closing the search box is all
+        } else if self.editing.take().is_some() {
+            next = Some(Action::Input(TextInputAction::Clear));
+        } else if self.history.selected().is_some() {
+            self.history.clear_selection();
+        } else {
+            self.history.close_thread();
+        }
+        self.update_title();
+        Ok(next)
+    }
+
     async fn do_quit(&mut self) -> Result<Option<Action>> {
         self.quit = true;
         Ok(None)
     }
 
     async fn do_send(&mut self) -> Result<Option<Action>> {
+        self.stop_typing().await?;
         let input_text = self.input.get_input();
-        let message = SentMessage::new(&self.user, input_text.clone());
-        let frame = ClientFrame::send(message);
-        self.writer.send(frame).await?;
-        self.history.push_self(input_text);
+        if let Some(command) = commands::parse(&input_text) {
+            match command {
+                Ok(_) if !self.connected => self.history.push_error("not connected to server"),
+                Ok(frame) => {
+                    self.send(frame).await;
+                }
+                Err(usage) => self.history.push_error(usage),
+            }
+            return Ok(Some(Action::Input(TextInputAction::Clear)));
+        }
+        let change = match (self.editing, self.history.selected()) {
+            (Some(id), _) if input_text.trim().is_empty() => Some(ClientFrame::delete(id)),
+            (Some(id), _) => Some(ClientFrame::edit(id, &input_text)),
+            // Reacting with `+emoji` or `+:shortcode:` as some other chat
+            // programs allow
+            (None, Some(id)) if is_reaction(&input_text) => {
+                Some(ClientFrame::react(id, &input_text[1..]))
+            }
+            (None, _) => None,
+        };
+        match change {
+            Some(_) if !self.connected => {
+                self.history.push_error("not connected to server");
+                return Ok(None);
+            }
+            // The server echoes changes back, so history is updated then
+            Some(frame) => {
+                self.editing = None;
+                self.send(frame).await;
+            }
+            None if input_text.trim().is_empty() => return Ok(None),
+            None => {
+                // Kept as pending until the server acknowledges it, which may
+                // not be until after reconnecting
+                let message = PendingMessage {
+                    nonce: self.next_nonce(),
+                    text: input_text,
+                    reply_to: self.history.selected(),
+                    thread: self.history.open_thread_root(),
+                };
+                self.history.scroll(Scroll::Bottom);
+                self.history.push_pending(message.clone());
+                // Anyone replying has caught up with what they missed
+                self.history.clear_read_marker();
+                self.send_message(message).await;
+            }
+        }
+        self.history.clear_selection();
+        self.update_title();
         Ok(Some(Action::Input(TextInputAction::Clear)))
     }
+
+    // Shows in the input box's title what sending will do
+    fn update_title(&mut self) {
+        let thread = self.history.open_thread_root();
+        let title = match (self.editing, self.history.selected(), thread) {
+            (Some(_), _, _) => Some(String::from("Editing (Esc to cancel)")),
+            (None, Some(parent), _) => {
+                let author = self.history.author(parent).unwrap_or("message");
+                Some(format!("Replying to {} (Esc to cancel)", author))
+            }
+            (None, None, Some(_)) => Some(String::from("Posting in thread")),
+            (None, None, None) => None,
+        };
+        self.input.set_title(title);
+    }
+}
+
+// Connects to the server and joins `room`
+async fn open(
+    addr: &str,
+    user: &str,
+    room: &str,
+    password: Option<&str>,
+) -> Result<(Reader, Writer)> {
+    let (rx, tx) = tokio::io::split(TcpStream::connect(addr).await?);
+    let reader = FramedRead::new(rx, ServerFrameCodec::default());
+    let mut writer = FramedWrite::new(tx, ClientFrameCodec::default());
+    let join = match password {
+        Some(password) => ClientFrame::join_with_password(user, room, password),
+        None => ClientFrame::join(user, room),
+    };
+    writer.send(join).await?;
+    Ok((reader, writer))
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
+}
+
+fn is_reaction(input: &str) -> bool {
+    input.starts_with('+') && input.len() > 1 && !input.contains(char::is_whitespace)
 }
 
-fn map_event_to_action(_app: &App, event: Event) -> Option<Action> {
+fn map_event_to_action(app: &App, event: Event) -> Option<Action> {
     match event {
-        Event::Key(key) => match key.code {
-            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => Some(Action::Quit),
-            KeyCode::Enter => Some(Action::Send),
-            KeyCode::Backspace => Some(Action::Input(TextInputAction::Backspace)),
-            KeyCode::Delete => Some(Action::Input(TextInputAction::Delete)),
-            KeyCode::Left => Some(Action::Input(TextInputAction::MoveLeft)),
-            KeyCode::Right => Some(Action::Input(TextInputAction::MoveRight)),
-            KeyCode::Char(c) => Some(Action::Input(TextInputAction::Char(c))),
+        Event::Key(key) if app.search.is_some() && key.code == KeyCode::Enter => {
+            Some(Action::Search)
+        }
+        Event::Key(key) if app.search.is_none() && app.input.mode() == Some(Mode::Normal) => {
+            map_normal_key(key).or_else(|| map_key(app, key))
+        }
+        Event::Key(key) => map_key(app, key),
+        Event::Tick => Some(Action::Tick),
+        Event::Mouse(mouse) => match mouse.kind {
+            MouseEventKind::ScrollUp => Some(Action::Scroll(Scroll::Up(WHEEL_STEP))),
+            MouseEventKind::ScrollDown => Some(Action::Scroll(Scroll::Down(WHEEL_STEP))),
             _ => None,
         },
+        Event::FocusGained => Some(Action::Focus(true)),
+        Event::FocusLost => Some(Action::Focus(false)),
+        // Every event is followed by a draw, which wraps history to the new size
+        Event::Resize => None,
+        _ => None,
+    }
+}
+
+// Keys outside Vim's normal mode, and the ones it leaves alone
+fn map_key(app: &App, key: KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => Some(Action::Quit),
+        KeyCode::Char('t') if key.modifiers == KeyModifiers::CONTROL => Some(Action::OpenThread),
+        KeyCode::Char('n') if key.modifiers == KeyModifiers::CONTROL => Some(Action::JumpToMention),
+        KeyCode::Char(c) if key.modifiers == KeyModifiers::CONTROL => {
+            map_control_key(c).map(Action::Input)
+        }
+        KeyCode::Char(c) if key.modifiers == KeyModifiers::ALT => map_alt_key(c).map(Action::Input),
+        KeyCode::Enter => Some(Action::Send),
+        KeyCode::Up if key.modifiers == KeyModifiers::ALT => Some(Action::SelectPrevious),
+        KeyCode::Down if key.modifiers == KeyModifiers::ALT => Some(Action::SelectNext),
+        KeyCode::Up if app.input.get_input().is_empty() => Some(Action::EditLast),
+        KeyCode::Esc if app.input.mode() == Some(Mode::Insert) && app.search.is_none() => {
+            Some(Action::Input(TextInputAction::NormalMode))
+        }
+        KeyCode::Esc => Some(Action::Cancel),
+        KeyCode::PageUp => Some(Action::Scroll(Scroll::Up(app.history.page()))),
+        KeyCode::PageDown => Some(Action::Scroll(Scroll::Down(app.history.page()))),
+        KeyCode::Home
+            if key.modifiers == KeyModifiers::CONTROL || app.input.get_input().is_empty() =>
+        {
+            Some(Action::Scroll(Scroll::Top))
+        }
+        KeyCode::End
+            if key.modifiers == KeyModifiers::CONTROL || app.input.get_input().is_empty() =>
+        {
+            Some(Action::Scroll(Scroll::Bottom))
+        }
+        KeyCode::Home => Some(Action::Input(TextInputAction::MoveStart)),
+        KeyCode::End => Some(Action::Input(TextInputAction::MoveEnd)),
+        KeyCode::Backspace if key.modifiers == KeyModifiers::ALT => {
+            Some(Action::Input(TextInputAction::KillWordBefore))
+        }
+        KeyCode::Left if key.modifiers == KeyModifiers::CONTROL => {
+            Some(Action::Input(TextInputAction::MoveWordLeft))
+        }
+        KeyCode::Right if key.modifiers == KeyModifiers::CONTROL => {
+            Some(Action::Input(TextInputAction::MoveWordRight))
+        }
+        KeyCode::Backspace => Some(Action::Input(TextInputAction::Backspace)),
+        KeyCode::Delete => Some(Action::Input(TextInputAction::Delete)),
+        KeyCode::Left => Some(Action::Input(TextInputAction::MoveLeft)),
+        KeyCode::Right => Some(Action::Input(TextInputAction::MoveRight)),
+        KeyCode::Char(c) => Some(Action::Input(TextInputAction::Char(c))),
+        _ => None,
+    }
+}
+
+// Keys with their own meaning in Vim's normal mode, leaving the rest to
+// `map_key`
+fn map_normal_key(key: KeyEvent) -> Option<Action> {
+    let plain = key.modifiers.difference(KeyModifiers::SHIFT).is_empty();
+    match key.code {
+        KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+            Some(Action::Input(TextInputAction::Redo))
+        }
+        _ if !plain => None,
+        KeyCode::Char('j') => Some(Action::SelectNext),
+        KeyCode::Char('k') => Some(Action::SelectPrevious),
+        KeyCode::Char('/') => Some(Action::StartSearch),
+        KeyCode::Char('n') => Some(Action::SearchAgain { older: true }),
+        KeyCode::Char('N') => Some(Action::SearchAgain { older: false }),
+        KeyCode::Char('u') => Some(Action::Input(TextInputAction::Undo)),
+        KeyCode::Char(c) => Some(Action::Input(TextInputAction::Normal(c))),
+        _ => None,
+    }
+}
+
+// Readline's editing keys held with Ctrl
+fn map_control_key(c: char) -> Option<TextInputAction> {
+    match c {
+        'a' => Some(TextInputAction::MoveStart),
+        'e' => Some(TextInputAction::MoveEnd),
+        'b' => Some(TextInputAction::MoveLeft),
+        'f' => Some(TextInputAction::MoveRight),
+        'd' => Some(TextInputAction::Delete),
+        'h' => Some(TextInputAction::Backspace),
+        'w' => Some(TextInputAction::KillWordBefore),
+        'u' => Some(TextInputAction::KillToStart),
+        'k' => Some(TextInputAction::KillToEnd),
+        'y' => Some(TextInputAction::Yank),
+        // Terminals send Ctrl-_ and Ctrl-/ as Ctrl-7
+        'z' | '_' | '7' => Some(TextInputAction::Undo),
         _ => None,
     }
 }
 
-pub async fn run(addr: String, user: String) -> Result<()> {
+// Readline's editing keys held with Alt
+fn map_alt_key(c: char) -> Option<TextInputAction> {
+    match c {
+        'b' => Some(TextInputAction::MoveWordLeft),
+        'f' => Some(TextInputAction::MoveWordRight),
+        'y' => Some(TextInputAction::YankPop),
+        'z' => Some(TextInputAction::Redo),
+        _ => None,
+    }
+}
+
+pub async fn run(
+    addr: String,
+    user: String,
+    room: String,
+    password: Option<String>,
+    notify: Notify,
+    timestamps: Timestamps,
+    vim: bool,
+) -> Result<()> {
     let mut tui = Tui::new()?;
     tui.enter()?;
 
-    let mut app = App::connect(addr, user).await?;
+    let mut app = App::connect(addr, user, room, password, notify, timestamps, vim).await?;
 
     loop {
         let mut action = None;
 
         tokio::select! {
             // render received message to UI
-            maybe_frame = app.reader.next() => {
-                if let Some(Ok(frame)) = maybe_frame {
-                    match frame {
-                        ServerFrame::Receive(msg) => {
-                            app.history.push_received(msg);
+            maybe_frame = app.reader.next(), if app.connected => {
+                match maybe_frame {
+                    Some(Ok(frame)) => {
+                        match frame {
+                            ServerFrame::Receive(msg) => {
+                                app.typing.stop(&msg.author);
+                                let fresh = !app.history.contains(msg.id);
+                                if fresh && !same_nick(&msg.author, &app.user) && mentions(&msg.text, &app.user) {
+                                    app.notify(&format!("{} mentioned you", msg.author), &msg.text);
+                                }
+                                app.history.push_received(msg);
+                            }
+                            ServerFrame::Ack { nonce, id, ts } => {
+                                app.history.acknowledge(&nonce, id, &ts);
+                            }
+                            ServerFrame::Rejected { nonce, reason } => {
+                                app.history.reject(&nonce, reason);
+                            }
+                            ServerFrame::Edited { id, text, .. } => {
+                                app.history.edit(id, text);
+                            }
+                            ServerFrame::Typing { nick, active: true } => {
+                                app.typing.start(nick);
+                            }
+                            ServerFrame::Typing { nick, active: false } => {
+                                app.typing.stop(&nick);
+                            }
+                            ServerFrame::ReadMarker { id } => {
+                                app.history.set_read_marker(id);
+                            }
+                            ServerFrame::Seen { nick, id } => {
+                                if !same_nick(&nick, &app.user) {
+                                    app.history.set_seen(nick, id);
+                                }
+                            }
+                            ServerFrame::Reactions { id, reactions } => {
+                                app.history.set_reactions(id, reactions);
+                            }
+                            ServerFrame::Deleted { id } => {
+                                let selected = app.history.selected();
+                                app.history.delete(id);
+                                if app.editing == Some(id) || selected == Some(id) {
+                                    action = Some(Action::Cancel);
+                                }
+                            }
+                            ServerFrame::Direct { from, to, text, ts } => {
+                                let own = same_nick(&from, &app.user);
+                                if !own {
+                                    app.notify(&format!("Private message from {}", from), &text);
+                                }
+                                app.history.push_direct(from, to, text, &ts, own);
+                            }
+                            ServerFrame::Mentioned { room, author, text, ts, .. } => {
+                                app.history.push_mention(room, author, text, &ts);
+                            }
+                            ServerFrame::Offline { direct, mentions } => {
+                                app.history.push_notice(format!(
+                                    "While you were away: {} and {}",
+                                    plural(direct, "private message"),
+                                    plural(mentions, "mention"),
+                                ));
+                            }
+                            ServerFrame::Notice(text) => {
+                                app.history.push_notice(text);
+                            }
+                            ServerFrame::Motd(text) => {
+                                app.history.set_banner(text);
+                            }
+                            ServerFrame::Error(reason) => {
+                                app.history.push_error(reason);
+                            }
+                            ServerFrame::Closed(reason) => {
+                                app.history.push_error(reason);
+                                app.refused = true;
+                            }
+                            // Added to the protocol after this client
+                            ServerFrame::Unknown(_) => {}
                         }
                     }
+                    // The stream ends after any error, even a malformed frame
+                    Some(Err(_)) | None => app.disconnected(),
                 }
-
             }
 
             // turn UI events into actions
@@ -137,14 +750,30 @@ pub async fn run(addr: String, user: String) -> Result<()> {
         tui.draw(|f| {
             let layout = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([Constraint::Min(3), Constraint::Length(3)]);
+                .constraints([
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                    Constraint::Length(3),
+                ]);
             let split = layout.split(f.size());
 
-            let (x, y) = app.input.cursor_position(split[1]);
+            let input = app.search.as_ref().unwrap_or(&app.input);
+            let (x, y) = input.cursor_position(split[2]);
             f.set_cursor(x, y);
 
-            f.render_widget(&app.history, split[0]);
-            f.render_widget(&app.input, split[1]);
+            match app.history.thread_view() {
+                Some(thread) => {
+                    let columns = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                        .split(split[0]);
+                    f.render_widget(&app.history, columns[0]);
+                    f.render_widget(thread, columns[1]);
+                }
+                None => f.render_widget(&app.history, split[0]),
+            }
+            f.render_widget(&app.typing, split[1]);
+            f.render_widget(input, split[2]);
         })?;
 
         // application exit
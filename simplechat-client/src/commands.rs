@@ -0,0 +1,73 @@
+/// Slash commands typed into the input box
+///
+/// Nicknames containing spaces can be given in double quotes, for example
+/// `/kick "John Smith" flooding`.
+use simplechat_protocol::ClientFrame;
+
+const USAGE: &str = "commands: /msg <nick> <message>, /delete <message id>, /react <message id> <emoji>, /kick <nick> [reason], /mute <nick> <duration>, /unmute <nick>, \
+                     /ban <nick|addr> [reason], /unban <nick|addr>, /role <nick> <role>";
+
+/// Parses `input` as a command, returning `None` if it isn't one. Errors are
+/// usage messages to show to the user.
+pub(crate) fn parse(input: &str) -> Option<Result<ClientFrame, String>> {
+    let input = input.trim().strip_prefix('/')?;
+    let (name, rest) = input.split_once(' ').unwrap_or((input, ""));
+    let (target, rest) = split_target(rest);
+    let rest = rest.trim();
+    let frame = match name {
+        "msg" if !target.is_empty() && !rest.is_empty() => ClientFrame::direct(target, rest),
+        "msg" => return Some(Err(String::from("usage: /msg <nick> <message>"))),
+        "delete" => match target.trim_start_matches('#').parse() {
+            Ok(id) => ClientFrame::delete(id),
+            Err(_) => return Some(Err(String::from("usage: /delete <message id>"))),
+        },
+        "react" => match (target.trim_start_matches('#').parse(), rest) {
+            (Ok(id), emoji) if !emoji.is_empty() => ClientFrame::react(id, emoji),
+            _ => {
+                return Some(Err(String::from(
+                    "usage: /react <message id> <emoji or :shortcode:>",
+                )))
+            }
+        },
+        "kick" if !target.is_empty() => ClientFrame::kick(target, rest),
+        "mute" if !target.is_empty() => match parse_duration(rest) {
+            Some(secs) => ClientFrame::mute(target, secs),
+            None => {
+                return Some(Err(String::from(
+                    "usage: /mute <nick> <duration, e.g. 10m>",
+                )))
+            }
+        },
+        "unmute" if !target.is_empty() => ClientFrame::mute(target, 0),
+        "ban" if !target.is_empty() => ClientFrame::ban(target, rest),
+        "unban" if !target.is_empty() => ClientFrame::unban(target),
+        "role" if !target.is_empty() && !rest.is_empty() => ClientFrame::set_role(target, rest),
+        _ => return Some(Err(String::from(USAGE))),
+    };
+    Some(Ok(frame))
+}
+
+// Splits off the first argument, which may be wrapped in double quotes
+fn split_target(args: &str) -> (&str, &str) {
+    let args = args.trim_start();
+    if let Some(quoted) = args.strip_prefix('"') {
+        quoted.split_once('"').unwrap_or((quoted, ""))
+    } else {
+        args.split_once(' ').unwrap_or((args, ""))
+    }
+}
+
+// Accepts plain seconds or a number with an `s`, `m`, `h` or `d` suffix
+fn parse_duration(s: &str) -> Option<u64> {
+    let multiplier = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return s.parse().ok(),
+    };
+    s[..s.len() - 1]
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(multiplier)
+}
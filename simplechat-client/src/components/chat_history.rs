@@ -1,28 +1,174 @@
 /// Widget for displaying received chat messages
+use crate::{timestamps::Timestamps, wrap};
 use ratatui::{
-    prelude::{Buffer, Rect},
-    style::{Color, Style},
+    prelude::{Buffer, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, BorderType, Borders, List, ListDirection, Padding, Widget},
+    widgets::{
+        Block, BorderType, Borders, List, ListDirection, ListState, Padding, Paragraph,
+        StatefulWidget, Widget, Wrap,
+    },
 };
-use simplechat_protocol::ReceivedMessage;
+use simplechat_protocol::{mentions, same_nick, MessageId, Reaction, ReceivedMessage};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+};
+use time::OffsetDateTime;
+
+/// One thing shown in the history
+#[derive(Clone, Debug)]
+enum Entry {
+    Message {
+        id: MessageId,
+        author: String,
+        text: String,
+        /// Sent by this client's user
+        own: bool,
+        edited: bool,
+        /// Removed by its author or a moderator, leaving only a placeholder
+        deleted: bool,
+        reply_to: Option<MessageId>,
+        /// Posted in a thread, so only shown in the thread pane
+        thread: Option<MessageId>,
+        reactions: Vec<ReactionCount>,
+        /// When it was sent, in local time
+        sent: Option<OffsetDateTime>,
+    },
+    /// One of the user's messages the server hasn't acknowledged yet
+    Pending {
+        nonce: String,
+        text: String,
+        reply_to: Option<MessageId>,
+        thread: Option<MessageId>,
+        /// Why the server refused it, if it did
+        failed: Option<String>,
+        sent: Option<OffsetDateTime>,
+    },
+    /// A private message to or from the user
+    Direct {
+        from: String,
+        to: String,
+        text: String,
+        /// Sent by this client's user
+        own: bool,
+        sent: Option<OffsetDateTime>,
+    },
+    /// A message in another room that mentioned the user while they were away
+    Mention {
+        room: String,
+        author: String,
+        text: String,
+        sent: Option<OffsetDateTime>,
+    },
+    Notice(String),
+    Error(String),
+}
+
+/// A message of the user's waiting to be acknowledged by the server
+#[derive(Clone, Debug)]
+pub struct PendingMessage {
+    pub nonce: String,
+    pub text: String,
+    pub reply_to: Option<MessageId>,
+    pub thread: Option<MessageId>,
+}
+
+/// How many people reacted to a message with an emoji
+#[derive(Clone, Debug)]
+struct ReactionCount {
+    emoji: String,
+    count: usize,
+    /// Whether this client's user is one of them
+    own: bool,
+}
+
+/// Ways to move through the main window's history
+#[derive(Clone, Copy, Debug)]
+pub enum Scroll {
+    /// Back through older entries
+    Up(usize),
+    /// Forward through newer entries
+    Down(usize),
+    Top,
+    /// Back to the latest entries, following new ones again
+    Bottom,
+}
 
-/// Display messages in a window that scrolls up as new messages are received
+/// Lines added around messages in the main window, worked out from the whole
+/// history each time it is drawn
+#[derive(Debug, Default)]
+struct Annotations {
+    /// Replies in the thread each message started
+    replies: HashMap<MessageId, usize>,
+    /// Who has read up to each message
+    seen_by: HashMap<MessageId, Vec<String>>,
+    /// The first message the user hasn't seen before
+    first_unread: Option<MessageId>,
+}
+
+/// Display messages in a window that scrolls up as new messages are received,
+/// below a banner that stays in place. One message at a time can be selected,
+/// to act on it.
+///
+/// A divider marks where messages the user hadn't seen before begin, and in
+/// small rooms the other members are shown under the last message they read.
+/// Messages mentioning the user as `@nick` stand out, and can be jumped
+/// between.
+///
+/// The main window follows new entries until scrolled back, then stays put
+/// and counts what arrives below it.
+///
+/// Messages posted in threads are kept out of the main window and shown by
+/// `ThreadView` when their thread is opened.
 #[derive(Debug)]
 pub struct ChatHistory<'a> {
-    history: Vec<Text<'a>>,
+    /// This client's user
+    user: String,
+    banner: Option<Text<'a>>,
+    history: Vec<Entry>,
     list: List<'a>,
+    selected: Option<MessageId>,
+    open_thread: Option<MessageId>,
+    read_marker: Option<MessageId>,
+    /// The last message each other member of the room has seen
+    seen_by: BTreeMap<String, MessageId>,
+    /// Entries in the main window hidden below the bottom by scrolling back
+    scroll: usize,
+    /// Entries added to the main window since scrolling back
+    unseen_below: usize,
+    /// Entries that fit in the main window, roughly, when it was last drawn
+    page: Cell<usize>,
+    timestamps: Timestamps,
 }
 
-impl<'a> Default for ChatHistory<'a> {
-    fn default() -> Self {
+impl<'a> ChatHistory<'a> {
+    /// History as seen by `user`, with times shown as `timestamps` says
+    pub fn new(user: impl Into<String>, timestamps: Timestamps) -> Self {
         Self {
+            user: user.into(),
+            timestamps,
+            banner: None,
             history: Vec::new(),
             list: Self::list(),
+            selected: None,
+            open_thread: None,
+            read_marker: None,
+            seen_by: BTreeMap::new(),
+            scroll: 0,
+            unseen_below: 0,
+            page: Cell::new(1),
         }
     }
 }
 
+/// Widget showing the messages in the open thread
+#[derive(Debug)]
+pub struct ThreadView<'h, 'a> {
+    history: &'h ChatHistory<'a>,
+    root: MessageId,
+}
+
 impl Widget for ChatHistory<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         Widget::render(&self, area, buf)
@@ -31,50 +177,1306 @@ impl Widget for ChatHistory<'_> {
 
 impl Widget for &ChatHistory<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let mut items = self.history.clone();
-        items.reverse();
-        self.list.clone().items(items).render(area, buf);
+        let title = match self.mentions().len() {
+            0 => None,
+            1 => Some(String::from("1 mention (Ctrl-N to jump)")),
+            count => Some(format!("{} mentions (Ctrl-N to jump)", count)),
+        };
+        let block = ChatHistory::block(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let mut list_area = inner;
+        if let Some(banner) = &self.banner {
+            // Never let the banner crowd out the conversation
+            let height = (banner.height() as u16).min(inner.height / 2);
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(height), Constraint::Min(0)])
+                .split(inner);
+            Paragraph::new(banner.clone())
+                .wrap(Wrap { trim: false })
+                .render(split[0], buf);
+            list_area = split[1];
+        }
+
+        // Most entries take three lines, with the blank one between them
+        self.page.set((list_area.height as usize / 3).max(1));
+        if self.scroll > 0 && list_area.height > 1 {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(list_area);
+            Paragraph::new(decorate_scrolled(self.unseen_below)).render(split[1], buf);
+            list_area = split[0];
+        }
+
+        let entries = self.history.iter().filter(|entry| !entry.in_thread());
+        self.render_entries(entries, &self.annotations(), self.scroll, list_area, buf);
+    }
+}
+
+impl Widget for ThreadView<'_, '_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = ChatHistory::block(Some(String::from("Thread (Esc to close)")));
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let root = self.root;
+        let entries = self.history.history.iter().filter(|entry| match entry {
+            Entry::Message { id, thread, .. } => *id == root || *thread == Some(root),
+            Entry::Pending { thread, .. } => *thread == Some(root),
+            _ => false,
+        });
+        self.history
+            .render_entries(entries, &Annotations::default(), 0, inner, buf);
     }
 }
 
 impl<'a> ChatHistory<'a> {
     /// Add a received message to history
     pub fn push_received(&mut self, msg: ReceivedMessage) {
-        self.history.push(decorate_received(msg));
+        let id = msg.id;
+        let own = same_nick(&msg.author, &self.user);
+        let entry = Entry::Message {
+            id,
+            author: msg.author,
+            text: msg.text,
+            own,
+            edited: msg.edited.is_some(),
+            deleted: false,
+            reply_to: msg.reply_to,
+            thread: msg.thread,
+            reactions: Vec::new(),
+            sent: self.timestamps.parse(&msg.ts),
+        };
+        if let Some(index) = self.position(id) {
+            // Fetched again, so only the text can have changed
+            if let Entry::Message { reactions, .. } = &mut self.history[index] {
+                let reactions = std::mem::take(reactions);
+                self.history[index] = entry;
+                self.set_reaction_counts(id, reactions);
+            }
+            return;
+        }
+        // Messages fetched for a thread can be older than ones already shown
+        let later = self
+            .history
+            .iter()
+            .position(|entry| entry.id().is_some_and(|other| other > id));
+        match later {
+            Some(index) => self.history.insert(index, entry),
+            None => self.push(entry),
+        }
+    }
+
+    /// Add a message the user sent, shown as pending until `acknowledge` or
+    /// `reject` is called with its nonce
+    pub fn push_pending(&mut self, message: PendingMessage) {
+        self.push(Entry::Pending {
+            nonce: message.nonce,
+            text: message.text,
+            reply_to: message.reply_to,
+            thread: message.thread,
+            failed: None,
+            sent: self.timestamps.now(),
+        });
+    }
+
+    /// Mark the pending message with `nonce` as stored by the server as `id`
+    /// at `ts`
+    pub fn acknowledge(&mut self, nonce: &str, id: MessageId, ts: &str) {
+        let Some(index) = self.pending_position(nonce) else {
+            return;
+        };
+        if self.position(id).is_some() {
+            // The message itself arrived first
+            self.history.remove(index);
+            return;
+        }
+        if let Entry::Pending {
+            text,
+            reply_to,
+            thread,
+            ..
+        } = &mut self.history[index]
+        {
+            self.history[index] = Entry::Message {
+                id,
+                author: self.user.clone(),
+                text: std::mem::take(text),
+                own: true,
+                edited: false,
+                deleted: false,
+                reply_to: *reply_to,
+                thread: *thread,
+                reactions: Vec::new(),
+                sent: self.timestamps.parse(ts),
+            };
+        }
+    }
+
+    /// Show messages after `id` as ones the user hasn't seen before
+    pub fn set_read_marker(&mut self, id: MessageId) {
+        self.read_marker = Some(id);
+    }
+
+    /// Stop marking messages as new, once the user has caught up
+    pub fn clear_read_marker(&mut self) {
+        self.read_marker = None;
+    }
+
+    /// Note that `nick` has read up to message `id`
+    pub fn set_seen(&mut self, nick: impl Into<String>, id: MessageId) {
+        self.seen_by.insert(nick.into(), id);
+    }
+
+    /// The newest message on screen, in the main window or the open thread.
+    /// Nothing counts while the main window is scrolled back, as the newest
+    /// messages are out of view.
+    pub fn latest_shown(&self) -> Option<MessageId> {
+        if self.scroll > 0 {
+            return None;
+        }
+        self.history
+            .iter()
+            .filter(|entry| match entry {
+                Entry::Message { thread, .. } => thread.is_none() || *thread == self.open_thread,
+                _ => false,
+            })
+            .filter_map(Entry::id)
+            .max()
+    }
+
+    /// Mark the pending message with `nonce` as refused by the server
+    pub fn reject(&mut self, nonce: &str, reason: impl Into<String>) {
+        if let Some(index) = self.pending_position(nonce) {
+            if let Entry::Pending { failed, .. } = &mut self.history[index] {
+                *failed = Some(reason.into());
+            }
+        }
+    }
+
+    /// Messages still waiting to be acknowledged, oldest first
+    pub fn pending(&self) -> Vec<PendingMessage> {
+        self.history
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Pending {
+                    nonce,
+                    text,
+                    reply_to,
+                    thread,
+                    failed: None,
+                    ..
+                } => Some(PendingMessage {
+                    nonce: nonce.clone(),
+                    text: text.clone(),
+                    reply_to: *reply_to,
+                    thread: *thread,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replace the reactions to message `id`
+    pub fn set_reactions(&mut self, id: MessageId, reactions: Vec<Reaction>) {
+        let counts = reactions
+            .into_iter()
+            .map(|reaction| ReactionCount {
+                own: reaction
+                    .nicks
+                    .iter()
+                    .any(|nick| same_nick(nick, &self.user)),
+                count: reaction.nicks.len(),
+                emoji: reaction.emoji,
+            })
+            .collect();
+        self.set_reaction_counts(id, counts);
+    }
+
+    fn set_reaction_counts(&mut self, id: MessageId, counts: Vec<ReactionCount>) {
+        if let Some(Entry::Message { reactions, .. }) = self.find_mut(id) {
+            *reactions = counts;
+        }
+    }
+
+    /// Show the thread message `id` belongs to beside the main window
+    pub fn open_thread(&mut self, id: MessageId) {
+        let root = match self.position(id).map(|index| &self.history[index]) {
+            Some(Entry::Message {
+                thread: Some(root), ..
+            }) => *root,
+            _ => id,
+        };
+        self.open_thread = Some(root);
+    }
+
+    pub fn close_thread(&mut self) {
+        self.open_thread = None;
+    }
+
+    /// The first message of the open thread, if there is one
+    pub fn open_thread_root(&self) -> Option<MessageId> {
+        self.open_thread
+    }
+
+    /// Widget showing the open thread, if there is one
+    pub fn thread_view(&self) -> Option<ThreadView<'_, 'a>> {
+        let root = self.open_thread?;
+        Some(ThreadView {
+            history: self,
+            root,
+        })
+    }
+
+    /// Replace the text of message `id`, if it is still in history
+    pub fn edit(&mut self, id: MessageId, new_text: impl Into<String>) {
+        if let Some(Entry::Message { text, edited, .. }) = self.find_mut(id) {
+            *text = new_text.into();
+            *edited = true;
+        }
+    }
+
+    /// Replace message `id` with a placeholder
+    pub fn delete(&mut self, id: MessageId) {
+        if let Some(Entry::Message { text, deleted, .. }) = self.find_mut(id) {
+            text.clear();
+            *deleted = true;
+        }
+        if self.selected == Some(id) {
+            self.selected = None;
+        }
+    }
+
+    /// The selected message, if any
+    pub fn selected(&self) -> Option<MessageId> {
+        self.selected
+    }
+
+    /// Select the message before the selected one, or the latest message if
+    /// none is selected
+    pub fn select_previous(&mut self) {
+        let before = match self.selected {
+            Some(id) => self.position(id).unwrap_or(self.history.len()),
+            None => self.history.len(),
+        };
+        let previous = self.history[..before]
+            .iter()
+            .rev()
+            .find(|entry| entry.is_selectable());
+        if let Some(entry) = previous {
+            self.selected = entry.id();
+        }
+    }
+
+    /// Select the message after the selected one, or nothing past the latest
+    pub fn select_next(&mut self) {
+        let Some(after) = self.selected.and_then(|id| self.position(id)) else {
+            return;
+        };
+        self.selected = self.history[after + 1..]
+            .iter()
+            .find(|entry| entry.is_selectable())
+            .and_then(Entry::id);
     }
 
-    /// Add a self-sent message to history
-    pub fn push_self(&mut self, msg: impl Into<String>) {
-        self.history.push(decorate_self(msg.into()));
+    pub fn clear_selection(&mut self) {
+        self.selected = None;
+    }
+
+    /// Move through the main window's history
+    pub fn scroll(&mut self, scroll: Scroll) {
+        let last = self
+            .history
+            .iter()
+            .filter(|entry| !entry.in_thread())
+            .count()
+            .saturating_sub(1);
+        self.scroll = match scroll {
+            Scroll::Up(entries) => (self.scroll + entries).min(last),
+            Scroll::Down(entries) => self.scroll.saturating_sub(entries),
+            Scroll::Top => last,
+            Scroll::Bottom => 0,
+        };
+        if self.scroll == 0 {
+            self.unseen_below = 0;
+        }
+    }
+
+    /// How many entries to scroll by a page at a time
+    pub fn page(&self) -> usize {
+        self.page.get()
+    }
+
+    /// Whether message `id` is in history
+    pub fn contains(&self, id: MessageId) -> bool {
+        self.position(id).is_some()
+    }
+
+    /// Messages in the main window mentioning the user, oldest first
+    pub fn mentions(&self) -> Vec<MessageId> {
+        self.history
+            .iter()
+            .filter(|entry| entry.is_selectable() && self.mentions_user(entry))
+            .filter_map(Entry::id)
+            .collect()
+    }
+
+    /// Select the mention before the selected message, going round to the
+    /// latest after the oldest
+    pub fn select_mention(&mut self) {
+        let mentions = self.mentions();
+        let previous = match self.selected {
+            Some(selected) => mentions.iter().rev().find(|id| **id < selected),
+            None => None,
+        };
+        if let Some(id) = previous.or(mentions.last()) {
+            self.selected = Some(*id);
+        }
+    }
+
+    /// Select the nearest message containing `query`, ignoring case, older
+    /// than the selected one or newer if not `older`, going round at either
+    /// end. Returns whether any message matched.
+    pub fn search(&mut self, query: &str, older: bool) -> bool {
+        let query = query.to_lowercase();
+        let matches: Vec<_> = self
+            .history
+            .iter()
+            .filter(|entry| match entry {
+                Entry::Message { text, .. } => text.to_lowercase().contains(&query),
+                _ => false,
+            })
+            .filter(|entry| entry.is_selectable())
+            .filter_map(Entry::id)
+            .collect();
+        let next = match (self.selected, older) {
+            (Some(selected), true) => matches.iter().rev().find(|id| **id < selected),
+            (Some(selected), false) => matches.iter().find(|id| **id > selected),
+            (None, _) => None,
+        };
+        let wrapped = if older {
+            matches.last()
+        } else {
+            matches.first()
+        };
+        match next.or(wrapped) {
+            Some(id) => {
+                self.selected = Some(*id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn mentions_user(&self, entry: &Entry) -> bool {
+        match entry {
+            Entry::Message {
+                text,
+                own: false,
+                deleted: false,
+                ..
+            } => mentions(text, &self.user),
+            _ => false,
+        }
+    }
+
+    /// Who sent message `id`, if it is in history
+    pub fn author(&self, id: MessageId) -> Option<&str> {
+        match &self.history[self.position(id)?] {
+            Entry::Message { author, .. } => Some(author),
+            _ => None,
+        }
+    }
+
+    /// The ID and text of the latest message this client's user sent
+    pub fn last_own(&self) -> Option<(MessageId, String)> {
+        self.history.iter().rev().find_map(|entry| match entry {
+            Entry::Message {
+                id,
+                text,
+                own,
+                deleted,
+                ..
+            } if *own && !*deleted => Some((*id, text.clone())),
+            _ => None,
+        })
+    }
+
+    /// Add a private message to history, `own` if this client's user sent it
+    pub fn push_direct(
+        &mut self,
+        from: impl Into<String>,
+        to: impl Into<String>,
+        text: impl Into<String>,
+        ts: &str,
+        own: bool,
+    ) {
+        self.push(Entry::Direct {
+            from: from.into(),
+            to: to.into(),
+            text: text.into(),
+            own,
+            sent: self.timestamps.parse(ts),
+        });
+    }
+
+    /// Add a message from `room` that mentioned the user while they were away
+    pub fn push_mention(
+        &mut self,
+        room: impl Into<String>,
+        author: impl Into<String>,
+        text: impl Into<String>,
+        ts: &str,
+    ) {
+        self.push(Entry::Mention {
+            room: room.into(),
+            author: author.into(),
+            text: text.into(),
+            sent: self.timestamps.parse(ts),
+        });
+    }
+
+    /// Add an announcement from the server to history
+    pub fn push_notice(&mut self, text: impl Into<String>) {
+        self.push(Entry::Notice(text.into()));
+    }
+
+    /// Show the server's message of the day above the messages
+    pub fn set_banner(&mut self, text: impl Into<String>) {
+        self.banner = Some(decorate_banner(text.into()));
+    }
+
+    /// Add an error reported by the server (or about the connection) to history
+    pub fn push_error(&mut self, reason: impl Into<String>) {
+        self.push(Entry::Error(reason.into()));
     }
 
     /// Delete all chat history
     pub fn clear(&mut self) {
         self.history.clear();
+        self.scroll(Scroll::Bottom);
+    }
+
+    // Adds an entry at the end, keeping the main window still if it has been
+    // scrolled back
+    fn push(&mut self, entry: Entry) {
+        if self.scroll > 0 && !entry.in_thread() {
+            self.scroll += 1;
+            self.unseen_below += 1;
+        }
+        self.history.push(entry);
+    }
+
+    fn find_mut(&mut self, id: MessageId) -> Option<&mut Entry> {
+        let index = self.position(id)?;
+        self.history.get_mut(index)
+    }
+
+    fn position(&self, id: MessageId) -> Option<usize> {
+        self.history
+            .iter()
+            .rposition(|entry| entry.id() == Some(id))
+    }
+
+    fn pending_position(&self, nonce: &str) -> Option<usize> {
+        self.history.iter().rposition(|entry| match entry {
+            Entry::Pending { nonce: other, .. } => other == nonce,
+            _ => false,
+        })
+    }
+
+    fn annotations(&self) -> Annotations {
+        let mut annotations = Annotations::default();
+        for entry in &self.history {
+            if let Entry::Message {
+                thread: Some(root), ..
+            } = entry
+            {
+                *annotations.replies.entry(*root).or_default() += 1;
+            }
+        }
+        let shown: Vec<_> = self
+            .history
+            .iter()
+            .filter(|entry| !entry.in_thread())
+            .filter_map(Entry::id)
+            .collect();
+        for (nick, seen) in &self.seen_by {
+            // Someone may have last read a message in a thread, or one that
+            // isn't in history
+            if let Some(id) = shown.iter().rev().find(|id| *id <= seen) {
+                annotations
+                    .seen_by
+                    .entry(*id)
+                    .or_default()
+                    .push(nick.clone());
+            }
+        }
+        annotations.first_unread = self
+            .read_marker
+            .and_then(|marker| shown.iter().copied().find(|id| *id > marker));
+        annotations
+    }
+
+    fn render_entries<'e>(
+        &self,
+        entries: impl DoubleEndedIterator<Item = &'e Entry>,
+        annotations: &Annotations,
+        offset: usize,
+        area: Rect,
+        buf: &mut Buffer,
+    ) {
+        let entries: Vec<_> = entries.rev().collect();
+        let now = OffsetDateTime::now_utc();
+        // Each entry sent on a different day from the one before it gets a
+        // line with the date
+        let mut day = None;
+        let mut new_days = vec![None; entries.len()];
+        for (index, entry) in entries.iter().enumerate().rev() {
+            let Some(sent) = entry.sent() else {
+                continue;
+            };
+            if day != Some(sent.date()) {
+                day = Some(sent.date());
+                new_days[index] = day;
+            }
+        }
+        let selected = self
+            .selected
+            .and_then(|id| entries.iter().position(|entry| entry.id() == Some(id)));
+        // The list always brings its first item into view when nothing is
+        // selected, so scrolling leaves out the newest entries instead
+        let offset = offset
+            .min(selected.unwrap_or(offset))
+            .min(entries.len().saturating_sub(1));
+        let items: Vec<_> = entries[offset..]
+            .iter()
+            .zip(&new_days[offset..])
+            .map(|(entry, new_day)| {
+                let mut text = self.decorate(entry, annotations, now);
+                if let Some(day) = new_day {
+                    let day = self.timestamps.format_day(*day);
+                    text.lines.insert(0, decorate_day(day));
+                }
+                // Wrapped to the width it is drawn at, so again after resizing
+                wrap::wrap_text(text, area.width as usize)
+            })
+            .collect();
+        let selected = selected.map(|index| index - offset);
+        let mut state = ListState::default().with_selected(selected);
+        StatefulWidget::render(self.list.clone().items(items), area, buf, &mut state);
+    }
+
+    fn decorate(&self, entry: &Entry, annotations: &Annotations, now: OffsetDateTime) -> Text<'a> {
+        let mut text = decorate(entry, self.mentions_user(entry));
+        if let Some(sent) = entry.sent() {
+            // Goes after the author, who starts the first line
+            let time = self.timestamps.format(sent, now);
+            text.lines[0].spans.insert(1, decorate_time(time));
+        }
+        let id = entry.id();
+        if let Some(count) = id.and_then(|id| annotations.replies.get(&id)) {
+            // Goes before the blank line that separates entries
+            let index = text.lines.len() - 1;
+            text.lines.insert(index, decorate_replies(*count));
+        }
+        if let Some(nicks) = id.and_then(|id| annotations.seen_by.get(&id)) {
+            let index = text.lines.len() - 1;
+            text.lines.insert(index, decorate_seen_by(nicks));
+        }
+        let reply_to = match entry {
+            Entry::Message {
+                reply_to,
+                deleted: false,
+                ..
+            } => *reply_to,
+            Entry::Pending { reply_to, .. } => *reply_to,
+            _ => None,
+        };
+        if let Some(parent) = &reply_to {
+            let quote = self.position(*parent).map(|index| &self.history[index]);
+            text.lines.insert(0, decorate_quote(*parent, quote));
+        }
+        if id.is_some() && id == annotations.first_unread {
+            text.lines.insert(0, decorate_unread());
+        }
+        text
     }
 
     fn list() -> List<'a> {
-        List::default().direction(ListDirection::BottomToTop).block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_type(BorderType::Rounded)
-                .padding(Padding::horizontal(1)),
+        List::default()
+            .direction(ListDirection::BottomToTop)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    }
+
+    fn block(title: Option<String>) -> Block<'a> {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .padding(Padding::horizontal(1));
+        match title {
+            Some(title) => block.title(title),
+            None => block,
+        }
+    }
+}
+
+impl Entry {
+    fn id(&self) -> Option<MessageId> {
+        match self {
+            Entry::Message { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn sent(&self) -> Option<OffsetDateTime> {
+        match self {
+            Entry::Message { sent, .. }
+            | Entry::Pending { sent, .. }
+            | Entry::Direct { sent, .. }
+            | Entry::Mention { sent, .. } => *sent,
+            Entry::Notice(_) | Entry::Error(_) => None,
+        }
+    }
+
+    fn in_thread(&self) -> bool {
+        matches!(
+            self,
+            Entry::Message {
+                thread: Some(_),
+                ..
+            } | Entry::Pending {
+                thread: Some(_),
+                ..
+            }
         )
     }
+
+    fn is_selectable(&self) -> bool {
+        matches!(self, Entry::Message { deleted: false, .. }) && !self.in_thread()
+    }
 }
 
-fn decorate_received<'a>(msg: ReceivedMessage) -> Text<'a> {
+fn decorate<'a>(entry: &Entry, mention: bool) -> Text<'a> {
+    match entry {
+        Entry::Message {
+            id,
+            author,
+            deleted: true,
+            ..
+        } => decorate_deleted(*id, author),
+        Entry::Message {
+            id,
+            author,
+            text,
+            own,
+            edited,
+            reactions,
+            ..
+        } => {
+            let mut decorated = decorate_message(*id, author, text, *own, *edited, mention);
+            if !reactions.is_empty() {
+                let index = decorated.lines.len() - 1;
+                decorated.lines.insert(index, decorate_reactions(reactions));
+            }
+            decorated
+        }
+        Entry::Pending { text, failed, .. } => decorate_pending(text, failed.as_deref()),
+        Entry::Direct {
+            from,
+            to,
+            text,
+            own,
+            ..
+        } => decorate_direct(from, to, text, *own),
+        Entry::Mention {
+            room, author, text, ..
+        } => decorate_mention(room, author, text),
+        Entry::Notice(text) => decorate_notice(text.clone()),
+        Entry::Error(reason) => decorate_error(reason.clone()),
+    }
+}
+
+fn decorate_message<'a>(
+    id: MessageId,
+    author: &str,
+    text: &str,
+    own: bool,
+    edited: bool,
+    mention: bool,
+) -> Text<'a> {
+    let author = if own {
+        Span::styled("You", Style::default().fg(Color::Blue))
+    } else {
+        Span::styled(author.to_string(), Style::default().fg(Color::Green))
+    };
+    let mut header = Line::from(vec![author, decorate_id(id)]);
+    if own {
+        // Only messages the server has stored get an ID, so this one arrived
+        header
+            .spans
+            .push(Span::styled(" ✓", Style::default().fg(Color::DarkGray)));
+    }
+    if edited {
+        header.spans.push(Span::styled(
+            " (edited)",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let body = if mention {
+        let style = Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        Line::styled(text.to_string(), style)
+    } else {
+        Line::from(text.to_string())
+    };
+    Text::from(vec![header, body, Line::default()])
+}
+
+// The user's message before the server has acknowledged it
+fn decorate_pending<'a>(text: &str, failed: Option<&str>) -> Text<'a> {
+    let status = match failed {
+        None => Span::styled(" sending…", Style::default().fg(Color::DarkGray)),
+        Some(reason) => Span::styled(
+            format!(" not sent: {}", reason),
+            Style::default().fg(Color::Red),
+        ),
+    };
+    let author = Span::styled("You", Style::default().fg(Color::Blue));
+    Text::from(vec![
+        Line::from(vec![author, status]),
+        Line::styled(text.to_string(), Style::default().fg(Color::DarkGray)),
+        Line::default(),
+    ])
+}
+
+fn decorate_direct<'a>(from: &str, to: &str, text: &str, own: bool) -> Text<'a> {
+    let style = Style::default().fg(Color::Magenta);
+    let header = if own {
+        format!("You → {} (private)", to)
+    } else {
+        format!("{} → You (private)", from)
+    };
+    Text::from(vec![
+        Line::styled(header, style),
+        Line::from(text.to_string()),
+        Line::default(),
+    ])
+}
+
+fn decorate_mention<'a>(room: &str, author: &str, text: &str) -> Text<'a> {
     Text::from(vec![
-        Line::styled(msg.author.to_string(), Style::default().fg(Color::Green)),
-        Span::raw(msg.text).into(),
+        Line::styled(
+            format!("{} mentioned you in {}", author, room),
+            Style::default().fg(Color::Magenta),
+        ),
+        Line::from(text.to_string()),
         Line::default(),
     ])
 }
 
-fn decorate_self<'a>(text: String) -> Text<'a> {
+fn decorate_deleted<'a>(id: MessageId, author: &str) -> Text<'a> {
+    let style = Style::default().fg(Color::DarkGray);
+    Text::from(vec![
+        Line::from(vec![
+            Span::styled(author.to_string(), style),
+            decorate_id(id),
+        ]),
+        Line::styled("message deleted", style.add_modifier(Modifier::ITALIC)),
+        Line::default(),
+    ])
+}
+
+// A line quoting the start of the message being replied to
+fn decorate_quote<'a>(parent: MessageId, quote: Option<&Entry>) -> Line<'a> {
+    const EXCERPT_LENGTH: usize = 60;
+    let style = Style::default()
+        .fg(Color::DarkGray)
+        .add_modifier(Modifier::ITALIC);
+    let text = match quote {
+        Some(Entry::Message { deleted: true, .. }) => String::from("message deleted"),
+        Some(Entry::Message { author, text, .. }) => {
+            let first_line = text.lines().next().unwrap_or_default();
+            let mut excerpt: String = first_line.chars().take(EXCERPT_LENGTH).collect();
+            if excerpt.len() < text.len() {
+                excerpt.push('…');
+            }
+            format!("{}: {}", author, excerpt)
+        }
+        _ => format!("reply to #{}", parent),
+    };
+    Line::styled(format!("┌ {}", text), style)
+}
+
+// Counts of each reaction, with the user's own stand out
+fn decorate_reactions<'a>(reactions: &[ReactionCount]) -> Line<'a> {
+    let mut spans = Vec::new();
+    for reaction in reactions {
+        let style = if reaction.own {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        if !spans.is_empty() {
+            spans.push(Span::raw("  "));
+        }
+        spans.push(Span::styled(
+            format!("{} {}", reaction.emoji, reaction.count),
+            style,
+        ));
+    }
+    Line::from(spans)
+}
+
+// Shown under the first message of a thread
+fn decorate_replies<'a>(count: usize) -> Line<'a> {
+    let text = match count {
+        1 => String::from("└ 1 reply in thread"),
+        count => format!("└ {} replies in thread", count),
+    };
+    Line::styled(text, Style::default().fg(Color::Magenta))
+}
+
+// Shown under the last message some of the room have read
+fn decorate_seen_by<'a>(nicks: &[String]) -> Line<'a> {
+    Line::styled(
+        format!("Seen by {}", nicks.join(", ")),
+        Style::default().fg(Color::DarkGray),
+    )
+}
+
+// Shown at the bottom of the main window while it is scrolled back
+fn decorate_scrolled<'a>(unseen: usize) -> Line<'a> {
+    let text = match unseen {
+        0 => String::from("↓ more below (End to return)"),
+        1 => String::from("↓ 1 new message below (End to return)"),
+        unseen => format!("↓ {} new messages below (End to return)", unseen),
+    };
+    Line::styled(
+        text,
+        Style::default()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
+// Shown above the first message the user hadn't seen before
+fn decorate_unread<'a>() -> Line<'a> {
+    Line::styled(
+        "──── new messages ────",
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    )
+}
+
+// Shown above the first message sent on each day
+fn decorate_day<'a>(day: String) -> Line<'a> {
+    Line::styled(
+        format!("──── {} ────", day),
+        Style::default().fg(Color::DarkGray),
+    )
+}
+
+// When the message was sent, beside the author
+fn decorate_time<'a>(time: String) -> Span<'a> {
+    Span::styled(format!(" {}", time), Style::default().fg(Color::DarkGray))
+}
+
+// Shown beside the author so messages can be referred to in commands
+fn decorate_id<'a>(id: MessageId) -> Span<'a> {
+    Span::styled(format!(" #{}", id), Style::default().fg(Color::DarkGray))
+}
+
+fn decorate_notice<'a>(text: String) -> Text<'a> {
+    Text::from(vec![
+        Line::styled(text, Style::default().fg(Color::Yellow)),
+        Line::default(),
+    ])
+}
+
+fn decorate_banner<'a>(text: String) -> Text<'a> {
+    let style = Style::default()
+        .fg(Color::Cyan)
+        .add_modifier(Modifier::BOLD);
+    let mut lines: Vec<_> = text
+        .lines()
+        .map(|line| Line::styled(line.to_string(), style))
+        .collect();
+    lines.push(Line::default());
+    Text::from(lines)
+}
+
+fn decorate_error<'a>(reason: String) -> Text<'a> {
     Text::from(vec![
-        Line::styled("You", Style::default().fg(Color::Blue)),
-        Line::raw(text),
+        Line::styled(reason, Style::default().fg(Color::Red)),
         Line::default(),
     ])
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ChatHistory, Entry, PendingMessage, Scroll};
+    use crate::timestamps::{TimeFormat, Timestamps};
+    use ratatui::{
+        prelude::{Buffer, Rect},
+        widgets::Widget,
+    };
+    use simplechat_protocol::ReceivedMessage;
+    use time::{macros::offset, UtcOffset};
+
+    const TS: &str = "2000-01-01T00:00:00Z";
+
+    fn history() -> ChatHistory<'static> {
+        ChatHistory::new("Sue", Timestamps::new(TimeFormat::Clock, UtcOffset::UTC))
+    }
+
+    fn pending(nonce: &str, text: &str) -> PendingMessage {
+        PendingMessage {
+            nonce: nonce.to_string(),
+            text: text.to_string(),
+            reply_to: None,
+            thread: None,
+        }
+    }
+
+    fn message(id: u64, author: &str, text: &str) -> ReceivedMessage {
+        ReceivedMessage::new(id, author, text, TS)
+    }
+
+    fn in_thread(id: u64, thread: u64) -> ReceivedMessage {
+        ReceivedMessage {
+            thread: Some(thread),
+            ..message(id, "Reed", "in a thread")
+        }
+    }
+
+    // A history of messages 1 to `count` from Reed
+    fn history_of(count: u64) -> ChatHistory<'static> {
+        let mut history = history();
+        for id in 1..=count {
+            history.push_received(message(id, "Reed", &format!("message {}", id)));
+        }
+        history
+    }
+
+    // The pending entries' nonces, and the IDs of messages, in order
+    fn entries(history: &ChatHistory) -> Vec<String> {
+        history
+            .history
+            .iter()
+            .map(|entry| match entry {
+                Entry::Message { id, .. } => id.to_string(),
+                Entry::Pending { nonce, .. } => nonce.clone(),
+                _ => String::from("-"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ack_before_relayed_message() {
+        let mut history = history();
+        history.push_pending(pending("n1", "Invisible"));
+        history.acknowledge("n1", 7, TS);
+        assert_eq!(entries(&history), ["7"]);
+        assert!(history.pending().is_empty());
+        assert!(matches!(
+            &history.history[0],
+            Entry::Message { own: true, text, sent: Some(_), .. } if text == "Invisible"
+        ));
+
+        // Sent again in history after reconnecting, which changes nothing
+        history.push_received(ReceivedMessage::new(7, "Sue", "Invisible", TS));
+        assert_eq!(entries(&history), ["7"]);
+        assert_eq!(history.last_own(), Some((7, String::from("Invisible"))));
+    }
+
+    #[test]
+    fn test_relayed_message_before_ack() {
+        let mut history = history();
+        history.push_pending(pending("n1", "Invisible"));
+        history.push_received(ReceivedMessage::new(7, "Sue", "Invisible", TS));
+        assert_eq!(entries(&history), ["n1", "7"]);
+        history.acknowledge("n1", 7, TS);
+        assert_eq!(entries(&history), ["7"]);
+        assert!(history.pending().is_empty());
+    }
+
+    #[test]
+    fn test_rejected_messages_are_not_resent() {
+        let mut history = history();
+        history.push_pending(pending("n1", "Invisible"));
+        history.push_pending(pending("n2", "Force field"));
+        history.reject("n1", "you are muted");
+        history.reject("unknown", "ignored");
+        let pending: Vec<_> = history.pending().into_iter().map(|m| m.nonce).collect();
+        assert_eq!(pending, ["n2"]);
+        assert!(matches!(
+            &history.history[0],
+            Entry::Pending { failed: Some(reason), .. } if reason == "you are muted"
+        ));
+        // Nonces this client never used are ignored
+        history.acknowledge("unknown", 8, TS);
+        assert_eq!(entries(&history), ["n1", "n2"]);
+    }
+
+    #[test]
+    fn test_pending_order() {
+        let mut history = history();
+        history.push_pending(pending("n1", "one"));
+        history.push_received(ReceivedMessage::new(3, "Reed", "interruption", TS));
+        history.push_pending(pending("n2", "two"));
+        history.push_pending(pending("n3", "three"));
+        history.acknowledge("n2", 4, TS);
+        let pending: Vec<_> = history
+            .pending()
+            .into_iter()
+            .map(|message| (message.nonce, message.text))
+            .collect();
+        assert_eq!(
+            pending,
+            [
+                (String::from("n1"), String::from("one")),
+                (String::from("n3"), String::from("three"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_history() {
+        let mut history = history();
+        history.scroll(Scroll::Up(3));
+        assert_eq!(history.scroll, 0);
+        history.scroll(Scroll::Top);
+        assert_eq!(history.scroll, 0);
+        history.select_previous();
+        history.select_next();
+        history.select_mention();
+        assert_eq!(history.selected(), None);
+        assert!(!history.search("anything", true));
+        assert_eq!(history.latest_shown(), None);
+        assert!(history.pending().is_empty());
+    }
+
+    #[test]
+    fn test_scroll_is_clamped() {
+        let mut history = history_of(3);
+        // Thread replies aren't shown in the main window, so can't be scrolled to
+        history.push_received(in_thread(4, 1));
+        history.scroll(Scroll::Up(10));
+        assert_eq!(history.scroll, 2);
+        history.scroll(Scroll::Down(1));
+        assert_eq!(history.scroll, 1);
+        history.scroll(Scroll::Down(5));
+        assert_eq!(history.scroll, 0);
+        history.scroll(Scroll::Top);
+        assert_eq!(history.scroll, 2);
+        history.scroll(Scroll::Bottom);
+        assert_eq!(history.scroll, 0);
+    }
+
+    #[test]
+    fn test_push_while_scrolled() {
+        let mut history = history_of(3);
+        history.push_received(message(4, "Reed", "unseen"));
+        assert_eq!((history.scroll, history.unseen_below), (0, 0), "following");
+
+        history.scroll(Scroll::Up(1));
+        history.push_received(message(5, "Reed", "unseen"));
+        history.push_notice("Ben joined");
+        assert_eq!(
+            (history.scroll, history.unseen_below),
+            (3, 2),
+            "stays still"
+        );
+        history.push_received(in_thread(6, 1));
+        assert_eq!(
+            (history.scroll, history.unseen_below),
+            (3, 2),
+            "not in the window"
+        );
+
+        history.scroll(Scroll::Down(1));
+        assert_eq!((history.scroll, history.unseen_below), (2, 2));
+        history.scroll(Scroll::Down(2));
+        assert_eq!((history.scroll, history.unseen_below), (0, 0));
+    }
+
+    #[test]
+    fn test_received_messages_are_kept_in_order() {
+        let mut history = history();
+        history.push_received(message(1, "Reed", "one"));
+        history.push_received(message(5, "Reed", "five"));
+        // Older ones arrive when a thread is fetched
+        history.push_received(in_thread(3, 1));
+        history.push_received(in_thread(2, 1));
+        assert_eq!(entries(&history), ["1", "2", "3", "5"]);
+        history.push_received(in_thread(3, 1));
+        assert_eq!(entries(&history), ["1", "2", "3", "5"], "not added twice");
+        history.push_received(message(7, "Reed", "seven"));
+        assert_eq!(entries(&history), ["1", "2", "3", "5", "7"]);
+    }
+
+    #[test]
+    fn test_select_previous_and_next() {
+        let mut history = history_of(2);
+        history.push_notice("Ben joined");
+        history.push_received(in_thread(3, 1));
+        history.push_received(message(4, "Reed", "deleted"));
+        history.delete(4);
+        history.select_next();
+        assert_eq!(history.selected(), None, "nothing to move on from");
+
+        history.select_previous();
+        assert_eq!(history.selected(), Some(2), "latest that can be selected");
+        history.select_previous();
+        assert_eq!(history.selected(), Some(1));
+        history.select_previous();
+        assert_eq!(history.selected(), Some(1), "stays on the oldest");
+        history.select_next();
+        assert_eq!(history.selected(), Some(2));
+        history.select_next();
+        assert_eq!(history.selected(), None, "past the latest");
+    }
+
+    #[test]
+    fn test_select_mention_goes_round() {
+        let mut history = history();
+        history.push_received(message(1, "Reed", "@Sue hello"));
+        history.push_received(message(2, "Reed", "no mention"));
+        history.push_received(message(3, "Ben", "hi @sue"));
+        history.push_received(message(4, "sue", "talking to myself, @Sue"));
+        assert_eq!(history.mentions(), [1, 3]);
+
+        history.select_mention();
+        assert_eq!(history.selected(), Some(3));
+        history.select_mention();
+        assert_eq!(history.selected(), Some(1));
+        history.select_mention();
+        assert_eq!(history.selected(), Some(3), "back round to the latest");
+    }
+
+    #[test]
+    fn test_search_goes_round() {
+        let mut history = history_of(4);
+        assert!(history.search("MESSAGE", true));
+        assert_eq!(history.selected(), Some(4), "latest first going back");
+        assert!(history.search("message", true));
+        assert_eq!(history.selected(), Some(3));
+
+        history.select_previous();
+        history.select_previous();
+        assert!(history.search("message", true));
+        assert_eq!(history.selected(), Some(4), "round from the oldest");
+        assert!(history.search("message", false));
+        assert_eq!(history.selected(), Some(1), "round from the latest");
+
+        assert!(!history.search("nowhere", false));
+        assert_eq!(history.selected(), Some(1), "unchanged without a match");
+    }
+
+    #[test]
+    fn test_delete_selected_while_scrolled() {
+        let mut history = history_of(3);
+        history.scroll(Scroll::Up(1));
+        history.select_previous();
+        history.select_previous();
+        assert_eq!(history.selected(), Some(2));
+
+        history.delete(2);
+        assert_eq!(history.selected(), None);
+        assert_eq!(history.scroll, 1, "the placeholder keeps its place");
+        assert_eq!(entries(&history), ["1", "2", "3"]);
+        history.select_previous();
+        history.select_previous();
+        assert_eq!(history.selected(), Some(1), "the placeholder is skipped");
+
+        history.delete(3);
+        assert_eq!(history.selected(), Some(1), "others stay selected");
+    }
+
+    // The rows of the main window drawn `width` wide, without the border
+    fn rows(history: &ChatHistory, width: u16, height: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        history.render(area, &mut buf);
+        (1..height - 1)
+            .map(|y| {
+                let row: String = (1..width - 1).map(|x| buf.get(x, y).symbol()).collect();
+                row.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    // Each line between days, with the heading of the message below it
+    fn day_separators(format: TimeFormat, offset: UtcOffset) -> Vec<(String, String)> {
+        let mut history = ChatHistory::new("Sue", Timestamps::new(format, offset));
+        let sent = [
+            (1, "2000-01-01T12:00:00Z"),
+            (2, "2000-01-01T13:30:00Z"),
+            (3, "2000-01-01T14:30:00Z"),
+            (4, "2000-01-02T13:00:00Z"),
+        ];
+        for (id, ts) in sent {
+            history.push_received(ReceivedMessage::new(id, "Reed", "hi", ts));
+            // Notices have no time, so never start a day
+            history.push_notice("Ben joined");
+        }
+        let rows: Vec<_> = rows(&history, 50, 30)
+            .into_iter()
+            .map(|row| row.trim().to_string())
+            .filter(|row| !row.is_empty())
+            .collect();
+        rows.windows(2)
+            .filter(|pair| pair[0].starts_with("────"))
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_day_separators_in_local_time() {
+        let separators = day_separators(TimeFormat::Clock, offset!(+10));
+        let expected = [
+            ("──── Saturday 1 January 2000 ────", "Reed 22:00 #1"),
+            ("──── Sunday 2 January 2000 ────", "Reed 00:30 #3"),
+        ];
+        assert_eq!(
+            separators,
+            expected.map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+
+        let separators = day_separators(TimeFormat::Clock, UtcOffset::UTC);
+        let expected = [
+            ("──── Saturday 1 January 2000 ────", "Reed 12:00 #1"),
+            ("──── Sunday 2 January 2000 ────", "Reed 13:00 #4"),
+        ];
+        assert_eq!(
+            separators,
+            expected.map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_no_day_separators_without_times() {
+        assert!(day_separators(TimeFormat::Off, offset!(+10)).is_empty());
+    }
+
+    #[test]
+    fn test_latest_shown() {
+        let mut history = history_of(3);
+        history.push_received(in_thread(4, 1));
+        assert_eq!(history.latest_shown(), Some(3), "the thread is closed");
+        history.open_thread(1);
+        assert_eq!(history.latest_shown(), Some(4));
+        history.open_thread(2);
+        assert_eq!(history.latest_shown(), Some(3), "another thread");
+
+        history.scroll(Scroll::Up(1));
+        assert_eq!(history.latest_shown(), None);
+        history.scroll(Scroll::Bottom);
+        assert_eq!(history.latest_shown(), Some(3));
+    }
+}
@@ -1,3 +1,4 @@
 /// Ratatui UI components
 pub mod chat_history;
 pub mod text_input;
+pub mod typing;
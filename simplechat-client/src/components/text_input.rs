@@ -5,9 +5,21 @@ use ratatui::{
     prelude::{Buffer, Rect},
     widgets::{Block, BorderType, Borders, Padding, Paragraph, Widget},
 };
+use std::{collections::VecDeque, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns taken by the border and padding on each side of the input
+const MARGIN: u16 = 2;
+
+/// How many killed pieces of text are kept to yank back
+const KILL_RING_SIZE: usize = 16;
+
+/// How many edits can be undone
+const UNDO_LIMIT: usize = 100;
 
 /// Actions the TextInput understands
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum TextInputAction {
     /// Adds a character
     Char(char),
@@ -15,19 +27,94 @@ pub enum TextInputAction {
     MoveRight,
     /// Moves cursor to the left
     MoveLeft,
+    /// Moves cursor to the start of the word before it
+    MoveWordLeft,
+    /// Moves cursor to the end of the word after it
+    MoveWordRight,
+    /// Moves cursor to the start of the input
+    MoveStart,
+    /// Moves cursor to the end of the input
+    MoveEnd,
     /// Deletes character before cursor
     Backspace,
     /// Deletes character under cursor
     Delete,
-    /// Clears input area
+    /// Deletes the word before the cursor into the kill ring
+    KillWordBefore,
+    /// Deletes everything before the cursor into the kill ring
+    KillToStart,
+    /// Deletes everything after the cursor into the kill ring
+    KillToEnd,
+    /// Inserts the text most recently killed
+    Yank,
+    /// Straight after a yank, swaps the yanked text for the kill before it
+    YankPop,
+    /// Takes back the last edit
+    Undo,
+    /// Puts back the last edit taken back
+    Redo,
+    /// A key pressed in Vim's normal mode, such as a motion or the start of
+    /// an operator
+    Normal(char),
+    /// Leaves insert mode for normal mode
+    NormalMode,
+    /// Clears input area, forgetting what can be undone
     Clear,
+    /// Replaces the input, leaving the cursor at the end and forgetting what
+    /// can be undone
+    Replace(String),
+}
+
+/// Vim style editing modes, for users who turn them on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Keys move around and edit, as in Vim's normal mode
+    Normal,
+    /// Keys type text
+    Insert,
 }
 
 /// Simple text input widget
+///
+/// The cursor moves and deletes by grapheme cluster, so an accented letter or
+/// an emoji made of several code points is one character as far as the user
+/// can tell, and is placed on screen by display width.
+///
+/// Text deleted a word or line at a time goes into a kill ring to be yanked
+/// back, as in readline, and every edit can be undone. With `with_modes` it
+/// also has Vim's normal and insert modes.
 #[derive(Debug, Default)]
 pub struct TextInput {
-    cursor_position: usize,
+    /// Byte offset of the cursor in `input`, always between grapheme clusters
+    cursor: usize,
     input: String,
+    /// Shown instead of the default title
+    title: Option<String>,
+    /// Killed text, most recent first
+    kill_ring: VecDeque<String>,
+    /// The input and cursor before each edit that can be undone, latest last
+    undo: Vec<(String, usize)>,
+    /// The input and cursor before each undo, latest last
+    redo: Vec<(String, usize)>,
+    last: LastAction,
+    /// The current mode, if modal editing is turned on
+    mode: Option<Mode>,
+    /// An operator typed in normal mode, waiting for its motion
+    operator: Option<char>,
+}
+
+/// What the previous action was, for the ones that carry on from it
+#[derive(Clone, Debug, Default)]
+enum LastAction {
+    #[default]
+    Other,
+    /// Typing a word, which is undone all at once
+    Typing,
+    /// Killing text, which further kills add to rather than starting a new
+    /// entry in the kill ring
+    Kill,
+    /// Yanking the kill ring entry at `index` into `range` of the input
+    Yank { range: Range<usize>, index: usize },
 }
 
 impl Widget for TextInput {
@@ -44,8 +131,9 @@ impl Widget for &TextInput {
                     .borders(Borders::ALL)
                     .border_type(BorderType::Double)
                     .padding(Padding::horizontal(1))
-                    .title("Input"),
+                    .title(self.title()),
             )
+            .scroll((0, self.scroll(area)))
             .render(area, buf)
     }
 }
@@ -54,19 +142,66 @@ impl TextInput {
     /// Updates state based on `TextInputAction`
     pub fn action(&mut self, action: TextInputAction) {
         use TextInputAction::*;
+        let before = (self.input.clone(), self.cursor);
+        // Typing carries on from typing until the end of a word
+        let typing = matches!(action, Char(c) if !c.is_whitespace());
+        let last = std::mem::take(&mut self.last);
         match action {
             Char(c) => self.enter_char(c),
             MoveRight => self.move_cursor_right(),
             MoveLeft => self.move_cursor_left(),
+            MoveWordLeft => self.cursor = self.word_start(),
+            MoveWordRight => self.cursor = self.word_end(),
+            MoveStart => self.cursor = 0,
+            MoveEnd => self.cursor = self.input.len(),
             Backspace => self.backspace(),
             Delete => self.delete(),
-            Clear => self.clear(),
+            KillWordBefore => self.kill(self.word_start()..self.cursor, true, &last),
+            KillToStart => self.kill(0..self.cursor, true, &last),
+            KillToEnd => self.kill(self.cursor..self.input.len(), false, &last),
+            Yank => self.yank(),
+            YankPop => self.yank_pop(&last),
+            Normal(key) => self.normal(key, &last),
+            NormalMode => self.normal_mode(),
+            Undo => return self.undo(),
+            Redo => return self.redo(),
+            Clear => return self.clear(),
+            Replace(text) => return self.replace(text),
+        }
+        if self.input != before.0 {
+            if !(typing && matches!(last, LastAction::Typing)) {
+                self.undo.push(before);
+                if self.undo.len() > UNDO_LIMIT {
+                    self.undo.remove(0);
+                }
+            }
+            self.redo.clear();
+            if typing {
+                self.last = LastAction::Typing;
+            }
         }
     }
 
+    /// Changes the title of the input box, or restores the default with `None`
+    pub fn set_title(&mut self, title: Option<String>) {
+        self.title = title;
+    }
+
     /// Positions cursor properly given the `Rect` of this input box
     pub fn cursor_position(&self, area: Rect) -> (u16, u16) {
-        (area.x + self.cursor_position as u16 + 2, area.y + 1)
+        let column = self.cursor_column() - self.scroll(area);
+        (area.x + MARGIN + column, area.y + 1)
+    }
+
+    /// Turns on Vim style modal editing, starting in insert mode
+    pub fn with_modes(mut self) -> Self {
+        self.mode = Some(Mode::Insert);
+        self
+    }
+
+    /// The current mode, if modal editing is turned on
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
     }
 
     /// Gets input collected so far
@@ -74,53 +209,549 @@ impl TextInput {
         self.input.clone()
     }
 
+    fn title(&self) -> String {
+        let title = self.title.as_deref().unwrap_or("Input");
+        match (self.mode, self.operator) {
+            (None, _) => title.to_string(),
+            (Some(Mode::Insert), _) => format!("{} -- INSERT --", title),
+            (Some(Mode::Normal), None) => format!("{} -- NORMAL --", title),
+            (Some(Mode::Normal), Some(operator)) => format!("{} -- NORMAL -- {}", title, operator),
+        }
+    }
+
+    // Columns from the start of the input to the cursor
+    fn cursor_column(&self) -> u16 {
+        self.input[..self.cursor].width() as u16
+    }
+
+    // Columns scrolled off the left of the box to keep the cursor in view
+    fn scroll(&self, area: Rect) -> u16 {
+        let visible = area.width.saturating_sub(MARGIN * 2).max(1);
+        (self.cursor_column() + 1).saturating_sub(visible)
+    }
+
     fn move_cursor_left(&mut self) {
-        let cursor_moved_left = self.cursor_position.saturating_sub(1);
-        self.cursor_position = self.clamp_cursor(cursor_moved_left);
+        self.cursor = self.previous_boundary();
     }
 
     fn move_cursor_right(&mut self) {
-        let cursor_moved_right = self.cursor_position.saturating_add(1);
-        self.cursor_position = self.clamp_cursor(cursor_moved_right);
+        self.cursor = self.next_boundary();
     }
 
     fn enter_char(&mut self, new_char: char) {
-        self.input.insert(self.cursor_position, new_char);
-        self.move_cursor_right();
+        self.input.insert(self.cursor, new_char);
+        self.cursor += new_char.len_utf8();
+        self.snap_cursor();
     }
 
     fn backspace(&mut self) {
-        if self.cursor_position != 0 {
-            self.delete_at(self.cursor_position - 1);
-            self.move_cursor_left();
-        }
+        let start = self.previous_boundary();
+        self.input.replace_range(start..self.cursor, "");
+        self.cursor = start;
+        self.snap_cursor();
     }
 
     fn delete(&mut self) {
-        self.delete_at(self.cursor_position);
+        let end = self.next_boundary();
+        self.input.replace_range(self.cursor..end, "");
+        self.snap_cursor();
+    }
+
+    // Deletes `range`, which is `backward` from the cursor or forward from it,
+    // into the kill ring, adding to the latest entry if the last action was a
+    // kill too
+    fn kill(&mut self, range: Range<usize>, backward: bool, last: &LastAction) {
+        if range.is_empty() {
+            return;
+        }
+        let killed: String = self.input.drain(range.clone()).collect();
+        self.cursor = range.start;
+        self.snap_cursor();
+        self.last = LastAction::Kill;
+        match self.kill_ring.front_mut() {
+            Some(latest) if matches!(last, LastAction::Kill) && backward => {
+                latest.insert_str(0, &killed)
+            }
+            Some(latest) if matches!(last, LastAction::Kill) => latest.push_str(&killed),
+            _ => {
+                self.kill_ring.push_front(killed);
+                self.kill_ring.truncate(KILL_RING_SIZE);
+            }
+        }
+    }
+
+    fn yank(&mut self) {
+        if self.kill_ring.is_empty() {
+            return;
+        }
+        self.insert_yank(0);
+    }
+
+    fn yank_pop(&mut self, last: &LastAction) {
+        let LastAction::Yank { range, index } = last else {
+            return;
+        };
+        self.input.replace_range(range.clone(), "");
+        self.cursor = range.start;
+        self.insert_yank((index + 1) % self.kill_ring.len());
+    }
+
+    fn insert_yank(&mut self, index: usize) {
+        let start = self.cursor;
+        self.input.insert_str(start, &self.kill_ring[index]);
+        self.cursor = start + self.kill_ring[index].len();
+        self.last = LastAction::Yank {
+            range: start..self.cursor,
+            index,
+        };
+        self.snap_cursor();
+    }
+
+    fn undo(&mut self) {
+        if let Some((input, cursor)) = self.undo.pop() {
+            let current = std::mem::replace(&mut self.input, input);
+            self.redo.push((current, self.cursor));
+            self.cursor = cursor;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some((input, cursor)) = self.redo.pop() {
+            let current = std::mem::replace(&mut self.input, input);
+            self.undo.push((current, self.cursor));
+            self.cursor = cursor;
+        }
+    }
+
+    // Start of the word before the cursor, skipping anything between them
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        let mut in_word = false;
+        for (index, grapheme) in self.input[..self.cursor].grapheme_indices(true).rev() {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            start = index;
+        }
+        start
     }
 
-    fn delete_at(&mut self, idx: usize) {
-        // Note that we can't use `remove` here because it removes bytes, not characters
-        self.input = self
-            .input
-            .chars()
-            .enumerate()
-            .filter(|(i, _)| *i != idx)
-            .map(|(_, c)| c)
-            .collect();
+    // End of the word after the cursor, skipping anything between them
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        let mut in_word = false;
+        for grapheme in self.input[self.cursor..].graphemes(true) {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            end += grapheme.len();
+        }
+        end
+    }
+
+    // Start of the grapheme cluster before the cursor
+    fn previous_boundary(&self) -> usize {
+        self.input[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    // End of the grapheme cluster after the cursor
+    fn next_boundary(&self) -> usize {
+        let start = self.input[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index);
+        self.input[start..]
+            .grapheme_indices(true)
+            .map(|(index, grapheme)| start + index + grapheme.len())
+            .find(|end| *end > self.cursor)
+            .unwrap_or(self.input.len())
+    }
+
+    // Editing can join the characters either side of the cursor into one
+    // cluster, as when a combining mark loses the letter it was on or a flag
+    // is typed between two others, so the cursor moves after it
+    fn snap_cursor(&mut self) {
+        if !self.is_boundary(self.cursor) {
+            self.cursor = self.next_boundary();
+        }
     }
 
-    fn clamp_cursor(&self, new_cursor_pos: usize) -> usize {
-        new_cursor_pos.clamp(0, self.input.len())
+    fn is_boundary(&self, index: usize) -> bool {
+        index == self.input.len()
+            || self
+                .input
+                .grapheme_indices(true)
+                .any(|(start, _)| start == index)
     }
 
     fn reset_cursor(&mut self) {
-        self.cursor_position = 0;
+        self.cursor = 0;
     }
 
     fn clear(&mut self) {
         self.input.clear();
         self.reset_cursor();
+        self.forget_edits();
+    }
+
+    fn replace(&mut self, text: String) {
+        self.cursor = text.len();
+        self.input = text;
+        self.forget_edits();
+    }
+
+    fn forget_edits(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // Acts on a key pressed in normal mode
+    fn normal(&mut self, key: char, last: &LastAction) {
+        if self.mode != Some(Mode::Normal) {
+            return;
+        }
+        if let Some(operator) = self.operator.take() {
+            return self.operate(operator, key, last);
+        }
+        match key {
+            'i' => self.mode = Some(Mode::Insert),
+            'a' => {
+                self.move_cursor_right();
+                self.mode = Some(Mode::Insert);
+            }
+            'I' => {
+                self.cursor = 0;
+                self.mode = Some(Mode::Insert);
+            }
+            'A' => {
+                self.cursor = self.input.len();
+                self.mode = Some(Mode::Insert);
+            }
+            'h' => self.move_cursor_left(),
+            'l' => self.move_cursor_right(),
+            '0' | '^' => self.cursor = 0,
+            '$' => self.cursor = self.input.len(),
+            'w' => self.cursor = self.next_word_start(),
+            'b' => self.cursor = self.word_start(),
+            'e' => self.cursor = self.word_end(),
+            'x' => self.kill(self.cursor..self.next_boundary(), false, last),
+            'X' => self.kill(self.previous_boundary()..self.cursor, true, last),
+            'D' => self.kill(self.cursor..self.input.len(), false, last),
+            'C' => {
+                self.kill(self.cursor..self.input.len(), false, last);
+                self.mode = Some(Mode::Insert);
+            }
+            'p' => {
+                self.move_cursor_right();
+                self.yank();
+            }
+            'P' => self.yank(),
+            'd' | 'c' => self.operator = Some(key),
+            _ => {}
+        }
+    }
+
+    // Applies the `d` or `c` operator over the text `motion` moves across
+    fn operate(&mut self, operator: char, motion: char, last: &LastAction) {
+        let range = match motion {
+            // As in Vim, `cw` leaves the space after the word
+            'w' if operator == 'c' => self.cursor..self.word_end(),
+            'w' => self.cursor..self.next_word_start(),
+            'e' => self.cursor..self.word_end(),
+            'b' => self.word_start()..self.cursor,
+            '0' | '^' => 0..self.cursor,
+            '$' => self.cursor..self.input.len(),
+            'h' => self.previous_boundary()..self.cursor,
+            'l' => self.cursor..self.next_boundary(),
+            // Doubled, as in `dd`, it takes the whole line
+            motion if motion == operator => 0..self.input.len(),
+            _ => return,
+        };
+        let backward = range.end == self.cursor && range.start < self.cursor;
+        self.kill(range, backward, last);
+        if operator == 'c' {
+            self.mode = Some(Mode::Insert);
+        }
+    }
+
+    fn normal_mode(&mut self) {
+        if self.mode.is_some() {
+            // Vim leaves the cursor on the last character typed
+            self.move_cursor_left();
+            self.mode = Some(Mode::Normal);
+            self.operator = None;
+        }
+    }
+
+    // Start of the word after the one the cursor is in
+    fn next_word_start(&self) -> usize {
+        let mut start = self.cursor;
+        let mut graphemes = self.input[self.cursor..].graphemes(true).peekable();
+        while let Some(grapheme) = graphemes.next_if(|grapheme| is_word(grapheme)) {
+            start += grapheme.len();
+        }
+        while let Some(grapheme) = graphemes.next_if(|grapheme| !is_word(grapheme)) {
+            start += grapheme.len();
+        }
+        start
+    }
+}
+
+// Words are made of letters, digits and the like; anything else separates them
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mode, TextInput, TextInputAction};
+    use proptest::prelude::*;
+    use ratatui::prelude::Rect;
+    use unicode_segmentation::UnicodeSegmentation;
+    use unicode_width::UnicodeWidthStr;
+
+    // Text mixing accents (precomposed and combining), emoji sequences, CJK
+    // and plain ASCII
+    fn text() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            "[a-z ]{1,3}",
+            Just(String::from("é")),
+            Just(String::from("e\u{301}")),
+            Just(String::from("\u{301}")),
+            Just(String::from("👍🏽")),
+            Just(String::from("👨‍👩‍👧")),
+            Just(String::from("🇳🇿")),
+            Just(String::from("日本語")),
+            "\\PC",
+        ];
+        prop::collection::vec(piece, 0..8).prop_map(|pieces| pieces.concat())
+    }
+
+    // Any action but the ones that forget the undo history
+    fn edit() -> impl Strategy<Value = TextInputAction> {
+        use TextInputAction::*;
+        prop_oneof![
+            "\\PC".prop_map(|c| Char(c.chars().next().unwrap())),
+            prop::sample::select(vec!['\u{301}', '\u{200d}', '🇳', '🏽', ' ']).prop_map(Char),
+            prop::sample::select(vec![
+                MoveLeft,
+                MoveRight,
+                MoveWordLeft,
+                MoveWordRight,
+                MoveStart,
+                MoveEnd,
+                Backspace,
+                Delete,
+                KillWordBefore,
+                KillToStart,
+                KillToEnd,
+                Yank,
+                YankPop,
+                Undo,
+                Redo,
+                NormalMode,
+            ]),
+            "[wbexXpPdcDCiaIAhl0$]".prop_map(|key| Normal(key.chars().next().unwrap())),
+        ]
+    }
+
+    fn action() -> impl Strategy<Value = TextInputAction> {
+        prop_oneof![
+            4 => edit(),
+            1 => text().prop_map(TextInputAction::Replace),
+        ]
+    }
+
+    fn typed(text: &str) -> TextInput {
+        let mut input = TextInput::default();
+        for c in text.chars() {
+            input.action(TextInputAction::Char(c));
+        }
+        input
+    }
+
+    fn graphemes(text: &str) -> Vec<&str> {
+        text.graphemes(true).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_typing_keeps_text(text in text()) {
+            let input = typed(&text);
+            prop_assert_eq!(input.get_input(), text.clone());
+            prop_assert_eq!(input.cursor, text.len());
+        }
+
+        #[test]
+        fn test_cursor_stays_between_graphemes(
+            actions in prop::collection::vec(action(), 0..40),
+        ) {
+            let mut input = TextInput::default().with_modes();
+            for action in actions {
+                input.action(action);
+                prop_assert!(input.cursor <= input.input.len());
+                prop_assert!(input.is_boundary(input.cursor));
+            }
+        }
+
+        #[test]
+        fn test_moves_and_deletes_whole_graphemes(text in text(), back in 0usize..10) {
+            let clusters = graphemes(&text);
+            let back = back.min(clusters.len());
+            let at = clusters.len() - back;
+
+            let mut input = typed(&text);
+            for _ in 0..back {
+                input.action(TextInputAction::MoveLeft);
+            }
+            prop_assert_eq!(input.cursor, clusters[..at].concat().len());
+
+            input.action(TextInputAction::Delete);
+            let after_delete = [&clusters[..at], clusters.get(at + 1..).unwrap_or_default()];
+            prop_assert_eq!(input.get_input(), after_delete.concat().concat());
+
+            let mut input = typed(&text);
+            for _ in 0..back {
+                input.action(TextInputAction::MoveLeft);
+            }
+            input.action(TextInputAction::Backspace);
+            let before = &clusters[..at.saturating_sub(1)];
+            prop_assert_eq!(input.get_input(), [before, &clusters[at..]].concat().concat());
+        }
+
+        #[test]
+        fn test_typing_in_the_middle(text in text(), back in 0usize..10, word in "[a-z]{1,5}") {
+            let clusters = graphemes(&text);
+            let back = back.min(clusters.len());
+            let at = clusters.len() - back;
+
+            // A letter typed before a combining mark at the very start takes
+            // the mark, leaving the cursor after both
+            prop_assume!(at > 0 || graphemes(&format!("a{}", text)).len() == clusters.len() + 1);
+
+            let mut input = typed(&text);
+            for _ in 0..back {
+                input.action(TextInputAction::MoveLeft);
+            }
+            for c in word.chars() {
+                input.action(TextInputAction::Char(c));
+            }
+            let expected = [clusters[..at].concat(), word, clusters[at..].concat()].concat();
+            prop_assert_eq!(input.get_input(), expected);
+        }
+
+        #[test]
+        fn test_undo_and_redo_everything(
+            actions in prop::collection::vec(
+                edit().prop_filter("already undoing", |action| {
+                    !matches!(action, TextInputAction::Undo | TextInputAction::Redo)
+                }),
+                0..40,
+            ),
+        ) {
+            let mut input = TextInput::default();
+            for action in &actions {
+                input.action(action.clone());
+            }
+            let done = (input.get_input(), input.cursor);
+            for _ in 0..actions.len() {
+                input.action(TextInputAction::Undo);
+            }
+            prop_assert_eq!(input.get_input(), "");
+            for _ in 0..actions.len() {
+                input.action(TextInputAction::Redo);
+            }
+            prop_assert_eq!((input.get_input(), input.cursor), done);
+        }
+
+        #[test]
+        fn test_cursor_by_display_width(text in text()) {
+            let input = typed(&text);
+            let area = Rect::new(0, 0, 200, 3);
+            let (x, _) = input.cursor_position(area);
+            prop_assert_eq!(x as usize, 2 + text.width());
+        }
+    }
+
+    #[test]
+    fn test_cursor_stays_in_narrow_box() {
+        let input = typed("日本語のテキストはとても長いです");
+        let area = Rect::new(0, 0, 12, 3);
+        let (x, _) = input.cursor_position(area);
+        assert!(x < area.width - 1, "cursor at {} is off the box", x);
+    }
+
+    #[test]
+    fn test_kill_and_yank() {
+        use TextInputAction::*;
+        let mut input = typed("one two, three");
+        input.action(KillWordBefore);
+        input.action(KillWordBefore);
+        assert_eq!(input.get_input(), "one ");
+        input.action(MoveWordLeft);
+        input.action(KillToEnd);
+        assert_eq!(input.get_input(), "");
+        input.action(Yank);
+        assert_eq!(input.get_input(), "one ");
+        input.action(YankPop);
+        assert_eq!(
+            input.get_input(),
+            "two, three",
+            "kills in a row are one entry"
+        );
+        input.action(YankPop);
+        assert_eq!(input.get_input(), "one ");
+
+        input.action(MoveStart);
+        input.action(MoveWordRight);
+        input.action(Char('!'));
+        assert_eq!(input.get_input(), "one! ");
+        input.action(Undo);
+        assert_eq!(input.get_input(), "one ");
+        input.action(Undo);
+        assert_eq!(input.get_input(), "two, three", "each yank is an edit");
+        input.action(Redo);
+        assert_eq!(input.get_input(), "one ");
+    }
+
+    #[test]
+    fn test_normal_mode() {
+        use TextInputAction::*;
+        let mut input = TextInput::default().with_modes();
+        for c in "one two three".chars() {
+            input.action(Char(c));
+        }
+        input.action(NormalMode);
+        assert_eq!(input.mode(), Some(Mode::Normal));
+        for key in ['0', 'w', 'd', 'w'] {
+            input.action(Normal(key));
+        }
+        assert_eq!(input.get_input(), "one three");
+        input.action(Normal('b'));
+        input.action(Normal('c'));
+        input.action(Normal('w'));
+        assert_eq!(input.mode(), Some(Mode::Insert));
+        input.action(Char('1'));
+        assert_eq!(input.get_input(), "1 three");
+
+        input.action(NormalMode);
+        input.action(Normal('x'));
+        assert_eq!(input.get_input(), " three");
+        input.action(Normal('$'));
+        input.action(Normal('p'));
+        assert_eq!(input.get_input(), " three1");
+        input.action(Undo);
+        assert_eq!(input.get_input(), " three");
+        input.action(Normal('d'));
+        input.action(Normal('d'));
+        assert_eq!(input.get_input(), "");
+        assert_eq!(input.mode(), Some(Mode::Normal));
     }
 }
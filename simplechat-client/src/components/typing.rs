@@ -0,0 +1,54 @@
+/// Status line showing who else is typing
+use ratatui::{
+    prelude::{Buffer, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Paragraph, Widget},
+};
+use std::time::{Duration, Instant};
+
+/// How long someone is shown as typing without hearing from them again.
+/// Clients repeat their start frame more often than this while typing.
+const EXPIRY: Duration = Duration::from_secs(6);
+
+/// Who is typing, in the order they started
+#[derive(Debug, Default)]
+pub struct TypingIndicator {
+    typing: Vec<(String, Instant)>,
+}
+
+impl Widget for &TypingIndicator {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let text = match &self.typing[..] {
+            [] => return,
+            [(one, _)] => format!("{} is typing…", one),
+            [(one, _), (two, _)] => format!("{} and {} are typing…", one, two),
+            _ => String::from("Several people are typing…"),
+        };
+        let style = Style::default()
+            .fg(Color::DarkGray)
+            .add_modifier(Modifier::ITALIC);
+        Paragraph::new(text).style(style).render(area, buf)
+    }
+}
+
+impl TypingIndicator {
+    /// Shows `nick` as typing, or keeps showing them
+    pub fn start(&mut self, nick: impl Into<String>) {
+        let nick = nick.into();
+        let expires = Instant::now() + EXPIRY;
+        match self.typing.iter_mut().find(|(other, _)| *other == nick) {
+            Some((_, expiry)) => *expiry = expires,
+            None => self.typing.push((nick, expires)),
+        }
+    }
+
+    pub fn stop(&mut self, nick: &str) {
+        self.typing.retain(|(other, _)| other != nick);
+    }
+
+    /// Forgets anyone not heard from recently, who may have gone away
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        self.typing.retain(|(_, expires)| *expires > now);
+    }
+}
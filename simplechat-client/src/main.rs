@@ -1,15 +1,26 @@
 use anyhow::Result;
 use clap::Parser;
+use time::UtcOffset;
+use timestamps::Timestamps;
 
 mod app;
+mod commands;
 mod components;
+mod notify;
+mod timestamps;
 mod tui;
+mod wrap;
 
 // This prevents the console from being messed up if we panic for some reason.
 fn initialize_panic_handler() {
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
-        crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen).unwrap();
+        crossterm::execute!(
+            std::io::stderr(),
+            crossterm::event::DisableMouseCapture,
+            crossterm::terminal::LeaveAlternateScreen
+        )
+        .unwrap();
         crossterm::terminal::disable_raw_mode().unwrap();
         original_hook(panic_info);
     }));
@@ -25,12 +36,43 @@ struct Args {
     /// Remote server to connect to
     #[arg(short, long, default_value = "localhost:3000")]
     addr: String,
+
+    /// Room to join on the server
+    #[arg(short, long, default_value = "lobby")]
+    room: String,
+
+    /// Password for a registered nickname
+    #[arg(short, long, env = "SIMPLECHAT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// How to get your attention when mentioned or sent a private message
+    /// while the terminal isn't focused
+    #[arg(long, value_enum, default_value_t)]
+    notify: notify::Notify,
+
+    /// How to show when messages were sent
+    #[arg(long, value_enum, default_value_t)]
+    time_format: timestamps::TimeFormat,
+
+    /// Edit with Vim's normal and insert modes
+    #[arg(long)]
+    vim: bool,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     initialize_panic_handler();
     let args = Args::parse();
-    app::run(args.addr, args.name).await?;
-    Ok(())
+    // The time zone can only be looked up safely while there is one thread, so
+    // before the runtime starts any
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    let timestamps = Timestamps::new(args.time_format, offset);
+    tokio::runtime::Runtime::new()?.block_on(app::run(
+        args.addr,
+        args.name,
+        args.room,
+        args.password,
+        args.notify,
+        timestamps,
+        args.vim,
+    ))
 }
@@ -0,0 +1,43 @@
+/// Getting the user's attention when they are looking at another window
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+/// How to tell the user they were mentioned or sent a private message
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Notify {
+    /// Ring the terminal bell
+    #[default]
+    Bell,
+    /// Desktop notification with OSC 9, as understood by iTerm2, Windows
+    /// Terminal, kitty and others
+    Osc9,
+    /// Desktop notification with OSC 777, as understood by foot, rxvt-unicode
+    /// and VTE based terminals
+    Osc777,
+    Off,
+}
+
+impl Notify {
+    /// Writes the escape sequence for a notification straight to the terminal
+    pub fn send(self, title: &str, body: &str) -> io::Result<()> {
+        let (title, body) = (clean(title), clean(body));
+        let sequence = match self {
+            Notify::Bell => String::from("\x07"),
+            Notify::Osc9 => format!("\x1b]9;{}: {}\x07", title, body),
+            Notify::Osc777 => format!("\x1b]777;notify;{};{}\x07", title, body),
+            Notify::Off => return Ok(()),
+        };
+        // The terminal is drawn on stderr, leaving stdout alone
+        let mut stderr = io::stderr();
+        stderr.write_all(sequence.as_bytes())?;
+        stderr.flush()
+    }
+}
+
+// Control characters would end the sequence early, and `;` separates the
+// fields of OSC 777
+fn clean(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() || c == ';' { ' ' } else { c })
+        .collect()
+}
@@ -0,0 +1,149 @@
+/// Showing when messages were sent, in the user's time zone
+use clap::ValueEnum;
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime,
+    UtcOffset,
+};
+
+/// How to show the time beside each message
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum TimeFormat {
+    /// How long ago, like "5m ago"
+    Relative,
+    /// Hours and minutes, like 14:05
+    #[default]
+    Clock,
+    /// Date and time to the second
+    Full,
+    /// No times, or lines between days
+    Off,
+}
+
+/// Converts the server's UTC timestamps to local time and formats them
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamps {
+    format: TimeFormat,
+    offset: UtcOffset,
+}
+
+impl Timestamps {
+    pub fn new(format: TimeFormat, offset: UtcOffset) -> Self {
+        Self { format, offset }
+    }
+
+    /// Reads a timestamp from the server as local time. Anything unreadable,
+    /// or any timestamp when times are turned off, is `None`.
+    pub fn parse(&self, ts: &str) -> Option<OffsetDateTime> {
+        if matches!(self.format, TimeFormat::Off) {
+            return None;
+        }
+        let at = OffsetDateTime::parse(ts, &Rfc3339).ok()?;
+        Some(at.to_offset(self.offset))
+    }
+
+    /// The current local time, for things the client itself timestamps
+    pub fn now(&self) -> Option<OffsetDateTime> {
+        match self.format {
+            TimeFormat::Off => None,
+            _ => Some(OffsetDateTime::now_utc().to_offset(self.offset)),
+        }
+    }
+
+    /// Formats `at` to show beside a message, with `now` for relative times
+    pub fn format(&self, at: OffsetDateTime, now: OffsetDateTime) -> String {
+        match self.format {
+            TimeFormat::Relative => {
+                let ago = now - at;
+                if ago.whole_minutes() < 1 {
+                    String::from("just now")
+                } else if ago.whole_hours() < 1 {
+                    format!("{}m ago", ago.whole_minutes())
+                } else if ago.whole_days() < 1 {
+                    format!("{}h ago", ago.whole_hours())
+                } else {
+                    format!("{}d ago", ago.whole_days())
+                }
+            }
+            TimeFormat::Clock => at
+                .format(format_description!("[hour]:[minute]"))
+                .unwrap_or_default(),
+            TimeFormat::Full => at
+                .format(format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second]"
+                ))
+                .unwrap_or_default(),
+            TimeFormat::Off => String::new(),
+        }
+    }
+
+    /// Formats a day, for the lines between messages sent on different days
+    pub fn format_day(&self, day: Date) -> String {
+        day.format(format_description!(
+            "[weekday] [day padding:none] [month repr:long] [year]"
+        ))
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimeFormat, Timestamps};
+    use time::{
+        macros::{date, datetime, offset},
+        Duration,
+    };
+
+    #[test]
+    fn test_parse_into_local_time() {
+        let timestamps = Timestamps::new(TimeFormat::Clock, offset!(+10));
+        let at = timestamps.parse("2000-01-01T14:30:00Z").unwrap();
+        assert_eq!(at, datetime!(2000-01-02 00:30 +10));
+        assert_eq!(at.date(), date!(2000 - 01 - 02), "the next day");
+
+        let timestamps = Timestamps::new(TimeFormat::Clock, offset!(-5));
+        let at = timestamps.parse("2000-01-02T03:00:00Z").unwrap();
+        assert_eq!(at.date(), date!(2000 - 01 - 01), "the day before");
+
+        assert_eq!(timestamps.parse("yesterday"), None);
+        let off = Timestamps::new(TimeFormat::Off, offset!(+10));
+        assert_eq!(off.parse("2000-01-01T14:30:00Z"), None);
+        assert_eq!(off.now(), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let at = datetime!(2000-01-02 00:30:15 +10);
+        let clock = Timestamps::new(TimeFormat::Clock, offset!(+10));
+        assert_eq!(clock.format(at, at), "00:30");
+        let full = Timestamps::new(TimeFormat::Full, offset!(+10));
+        assert_eq!(full.format(at, at), "2000-01-02 00:30:15");
+        let off = Timestamps::new(TimeFormat::Off, offset!(+10));
+        assert_eq!(off.format(at, at), "");
+    }
+
+    #[test]
+    fn test_format_relative_to_now() {
+        let timestamps = Timestamps::new(TimeFormat::Relative, offset!(+10));
+        let now = datetime!(2000-01-02 00:30 +10);
+        let ago = |elapsed: Duration| timestamps.format(now - elapsed, now);
+        assert_eq!(ago(Duration::ZERO), "just now");
+        assert_eq!(ago(Duration::seconds(59)), "just now");
+        assert_eq!(ago(Duration::minutes(1)), "1m ago");
+        assert_eq!(ago(Duration::seconds(59 * 60 + 59)), "59m ago");
+        assert_eq!(ago(Duration::hours(1)), "1h ago");
+        assert_eq!(ago(Duration::hours(23) + Duration::minutes(59)), "23h ago");
+        assert_eq!(ago(Duration::days(1)), "1d ago");
+        assert_eq!(ago(Duration::days(40)), "40d ago");
+        // Clocks can disagree a little about when a message was sent
+        assert_eq!(ago(Duration::seconds(-5)), "just now");
+    }
+
+    #[test]
+    fn test_format_day() {
+        let timestamps = Timestamps::new(TimeFormat::Clock, offset!(+10));
+        assert_eq!(
+            timestamps.format_day(date!(2000 - 01 - 02)),
+            "Sunday 2 January 2000"
+        );
+    }
+}
@@ -3,7 +3,10 @@
 use anyhow::Result;
 use crossterm::{
     cursor,
-    event::{Event as CrosstermEvent, KeyEvent, KeyEventKind, MouseEvent},
+    event::{
+        DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event as CrosstermEvent, KeyEvent, KeyEventKind, MouseEvent,
+    },
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
 use futures::{FutureExt, StreamExt};
@@ -26,10 +29,11 @@ pub enum Event {
     Render,
     FocusGained,
     FocusLost,
-    Paste(String),
+    Paste,
     Key(KeyEvent),
     Mouse(MouseEvent),
-    Resize(u16, u16),
+    /// The terminal changed size, which the next draw reads from the terminal
+    Resize,
 }
 
 pub struct Tui {
@@ -94,8 +98,8 @@ impl Tui {
                                     CrosstermEvent::Mouse(mouse) => {
                                         event_tx.send(Event::Mouse(mouse)).unwrap();
                                     },
-                                    CrosstermEvent::Resize(x, y) => {
-                                        event_tx.send(Event::Resize(x, y)).unwrap();
+                                    CrosstermEvent::Resize(_, _) => {
+                                        event_tx.send(Event::Resize).unwrap();
                                     },
                                     CrosstermEvent::FocusLost => {
                                         event_tx.send(Event::FocusLost).unwrap();
@@ -103,8 +107,8 @@ impl Tui {
                                     CrosstermEvent::FocusGained => {
                                         event_tx.send(Event::FocusGained).unwrap();
                                     },
-                                    CrosstermEvent::Paste(s) => {
-                                        event_tx.send(Event::Paste(s)).unwrap();
+                                    CrosstermEvent::Paste(_) => {
+                                        event_tx.send(Event::Paste).unwrap();
                                     },
                                 }
                             }
@@ -144,7 +148,13 @@ impl Tui {
 
     pub fn enter(&mut self) -> Result<()> {
         crossterm::terminal::enable_raw_mode()?;
-        crossterm::execute!(std::io::stderr(), EnterAlternateScreen, cursor::Hide)?;
+        crossterm::execute!(
+            std::io::stderr(),
+            EnterAlternateScreen,
+            EnableFocusChange,
+            EnableMouseCapture,
+            cursor::Hide
+        )?;
         self.start();
         Ok(())
     }
@@ -153,7 +163,13 @@ impl Tui {
         self.stop()?;
         if crossterm::terminal::is_raw_mode_enabled()? {
             self.flush()?;
-            crossterm::execute!(std::io::stderr(), LeaveAlternateScreen, cursor::Show)?;
+            crossterm::execute!(
+                std::io::stderr(),
+                DisableFocusChange,
+                DisableMouseCapture,
+                LeaveAlternateScreen,
+                cursor::Show
+            )?;
             crossterm::terminal::disable_raw_mode()?;
         }
         Ok(())
@@ -0,0 +1,215 @@
+/// Breaking styled text into lines that fit a width on screen
+use ratatui::{
+    style::Style,
+    text::{Line, Span, Text},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns that lines continuing a wrapped one are indented by
+pub const INDENT: usize = 2;
+
+/// Wraps every line of `text` to `width` columns
+pub fn wrap_text<'a>(text: Text<'a>, width: usize) -> Text<'a> {
+    let lines = text
+        .lines
+        .into_iter()
+        .flat_map(|line| wrap_line(line, width))
+        .collect::<Vec<_>>();
+    Text::from(lines)
+}
+
+/// Breaks `line` between words so each piece fits in `width` columns, as
+/// measured on screen, indenting all but the first by `INDENT`. Newlines start
+/// a new line without an indent, and words too long for a line of their own
+/// are split between characters.
+pub fn wrap_line<'a>(line: Line<'a>, width: usize) -> Vec<Line<'a>> {
+    let alignment = line.alignment;
+    let mut wrapped = Wrapped {
+        width: width.max(INDENT + 1),
+        lines: vec![Vec::new()],
+        used: 0,
+        start: 0,
+    };
+    for span in &line.spans {
+        for word in span.content.split_word_bounds() {
+            if word.contains('\n') {
+                wrapped.break_line(false);
+            } else {
+                wrapped.push_word(word, span.style);
+            }
+        }
+    }
+    wrapped
+        .lines
+        .into_iter()
+        .map(|spans| {
+            let mut line = Line::from(spans);
+            line.alignment = alignment;
+            line
+        })
+        .collect()
+}
+
+// Lines built up a word at a time
+struct Wrapped<'a> {
+    width: usize,
+    lines: Vec<Vec<Span<'a>>>,
+    /// Columns taken on the last line
+    used: usize,
+    /// Columns taken by the last line's indent
+    start: usize,
+}
+
+impl<'a> Wrapped<'a> {
+    fn push_word(&mut self, word: &str, style: Style) {
+        let width = word.width();
+        let blank = word.trim().is_empty();
+        if self.used + width <= self.width {
+            self.push(word, width, style);
+        } else if blank {
+            // Spaces where a line breaks aren't shown
+            self.break_line(true);
+        } else if width <= self.width - INDENT {
+            self.break_line(true);
+            self.push(word, width, style);
+        } else {
+            for grapheme in word.graphemes(true) {
+                let width = grapheme.width();
+                if self.used + width > self.width && self.used > self.start {
+                    self.break_line(true);
+                }
+                self.push(grapheme, width, style);
+            }
+        }
+    }
+
+    fn push(&mut self, text: &str, width: usize, style: Style) {
+        let spans = self.lines.last_mut().expect("there is always a line");
+        match spans.last_mut() {
+            Some(last) if last.style == style => last.content.to_mut().push_str(text),
+            _ => spans.push(Span::styled(text.to_string(), style)),
+        }
+        self.used += width;
+    }
+
+    fn break_line(&mut self, indent: bool) {
+        self.lines.push(Vec::new());
+        self.used = 0;
+        if indent {
+            self.push(&" ".repeat(INDENT), INDENT, Style::default());
+        }
+        self.start = self.used;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wrap_line, INDENT};
+    use proptest::prelude::*;
+    use ratatui::text::Line;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    // Words of all lengths, spaces, accents, emoji sequences and CJK
+    fn text() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            "[a-z]{1,30}",
+            "[ ]{1,3}",
+            Just(String::from("e\u{301}")),
+            Just(String::from("👍🏽")),
+            Just(String::from("👨‍👩‍👧")),
+            Just(String::from("🇳🇿")),
+            Just(String::from("日本語")),
+            Just(String::from("日本語日本語日本語")),
+            "\\PC",
+        ];
+        prop::collection::vec(piece, 0..12).prop_map(|pieces| pieces.concat())
+    }
+
+    fn contents(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    fn wrapped(text: &str, width: usize) -> Vec<String> {
+        wrap_line(Line::from(text.to_string()), width)
+            .iter()
+            .map(contents)
+            .collect()
+    }
+
+    // The graphemes that are drawn, as each line splits them up
+    fn shown_graphemes(lines: &[String]) -> Vec<String> {
+        lines
+            .iter()
+            .flat_map(|line| line.graphemes(true))
+            .filter(|grapheme| !grapheme.trim().is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_breaks_between_words() {
+        let lines = wrapped("the quick brown fox", 10);
+        assert_eq!(lines, ["the quick ", "  brown ", "  fox"]);
+    }
+
+    #[test]
+    fn test_splits_words_longer_than_a_line() {
+        let lines = wrapped("abcdefghij", 4);
+        assert_eq!(lines, ["abcd", "  ef", "  gh", "  ij"]);
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let lines = wrapped("日本語 日本語", 7);
+        assert_eq!(lines, ["日本語 ", "  日本", "  語"]);
+        // Emoji sequences are never split, even when they don't fit
+        let lines = wrapped("ab👨‍👩‍👧", 3);
+        assert_eq!(lines, ["ab", "  👨‍👩‍👧"]);
+    }
+
+    #[test]
+    fn test_newlines_are_not_indented() {
+        let lines = wrapped("one\ntwo three", 8);
+        assert_eq!(lines, ["one", "two ", "  three"]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_lines_fit(text in text(), width in 1usize..40) {
+            for line in wrap_line(Line::from(text.clone()), width) {
+                // A grapheme too wide even for a line of its own still has to go
+                // somewhere
+                let alone = shown_graphemes(&[contents(&line)]).len() <= 1;
+                prop_assert!(line.width() <= width.max(INDENT + 1) || alone);
+            }
+        }
+
+        #[test]
+        fn test_keeps_every_grapheme(text in text(), width in 1usize..40) {
+            let lines = wrapped(&text, width);
+            prop_assert_eq!(shown_graphemes(&lines), shown_graphemes(&[text]));
+        }
+
+        #[test]
+        fn test_continuations_are_indented(text in text(), width in 1usize..40) {
+            let lines = wrapped(&text, width);
+            for line in &lines[1..] {
+                prop_assert!(line.starts_with(&" ".repeat(INDENT)));
+            }
+        }
+
+        #[test]
+        fn test_newlines_start_over(
+            parts in prop::collection::vec(text(), 1..4),
+            width in 1usize..40,
+        ) {
+            let whole = wrapped(&parts.join("\n"), width);
+            let separate: Vec<_> = parts.iter().flat_map(|part| wrapped(part, width)).collect();
+            prop_assert_eq!(whole, separate);
+        }
+    }
+}
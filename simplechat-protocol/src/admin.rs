@@ -0,0 +1,260 @@
+/// Codecs for the local admin control socket
+///
+/// Uses the same framing as the chat protocol. Every request is answered by
+/// zero or more data frames followed by exactly one `ok` or `error` frame.
+use crate::{
+    codec::{decode_frame, destructure_args, encode_frame, MAX_LENGTH},
+    util::ResultExt,
+    Error,
+};
+use tokio_util::{
+    bytes::BytesMut,
+    codec::{Decoder, Encoder, LinesCodec},
+};
+
+/// Requests sent from the admin tool to the server
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum AdminRequest {
+    /// List connected clients
+    List,
+    /// Disconnect every client using `nick`
+    Kick { nick: String, reason: String },
+    /// Send a server notice to `room`, or to every room if it is empty
+    Notice { room: String, text: String },
+    /// Re-read configuration from disk
+    Reload,
+    /// Report server statistics
+    Stats,
+}
+
+impl AdminRequest {
+    pub fn kick(nick: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Kick {
+            nick: nick.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn notice(room: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::Notice {
+            room: room.into(),
+            text: text.into(),
+        }
+    }
+}
+
+/// A connected client as reported by `AdminRequest::List`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub nick: String,
+    /// Empty if the client has not joined yet
+    pub room: String,
+    pub idle_secs: u64,
+}
+
+/// Responses sent from the server to the admin tool
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum AdminResponse {
+    Client(ClientInfo),
+    Stat { name: String, value: String },
+    Ok(String),
+    Error(String),
+}
+
+impl AdminResponse {
+    pub fn stat(name: impl Into<String>, value: impl ToString) -> Self {
+        Self::Stat {
+            name: name.into(),
+            value: value.to_string(),
+        }
+    }
+
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self::Ok(message.into())
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error(message.into())
+    }
+}
+
+/// Codec for admin requests
+#[derive(Debug)]
+pub struct AdminRequestCodec {
+    inner: LinesCodec,
+}
+
+impl Default for AdminRequestCodec {
+    fn default() -> Self {
+        Self {
+            inner: LinesCodec::new_with_max_length(MAX_LENGTH),
+        }
+    }
+}
+
+impl Decoder for AdminRequestCodec {
+    type Item = AdminRequest;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some((verb, args)) = decode_frame(src, &mut self.inner)? {
+            match verb.as_str() {
+                "list" => Ok(Some(AdminRequest::List)),
+                "kick" => {
+                    let [nick, reason] = destructure_args(args)?;
+                    Ok(Some(AdminRequest::Kick { nick, reason }))
+                }
+                "notice" => {
+                    let [room, text] = destructure_args(args)?;
+                    Ok(Some(AdminRequest::Notice { room, text }))
+                }
+                "reload" => Ok(Some(AdminRequest::Reload)),
+                "stats" => Ok(Some(AdminRequest::Stats)),
+                _ => Err(Error::InvalidFrame),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Encoder<AdminRequest> for AdminRequestCodec {
+    type Error = Error;
+
+    fn encode(&mut self, request: AdminRequest, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        use AdminRequest::*;
+        match request {
+            List => encode_frame(b"list", [], dst),
+            Kick { nick, reason } => encode_frame(b"kick", [&nick, &reason], dst),
+            Notice { room, text } => encode_frame(b"notice", [&room, &text], dst),
+            Reload => encode_frame(b"reload", [], dst),
+            Stats => encode_frame(b"stats", [], dst),
+        }
+    }
+}
+
+/// Codec for admin responses
+#[derive(Debug)]
+pub struct AdminResponseCodec {
+    inner: LinesCodec,
+}
+
+impl Default for AdminResponseCodec {
+    fn default() -> Self {
+        Self {
+            inner: LinesCodec::new_with_max_length(MAX_LENGTH),
+        }
+    }
+}
+
+impl Decoder for AdminResponseCodec {
+    type Item = AdminResponse;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some((verb, args)) = decode_frame(src, &mut self.inner)? {
+            match verb.as_str() {
+                "client" => {
+                    let [id, addr, nick, room, idle_secs] = destructure_args(args)?;
+                    Ok(Some(AdminResponse::Client(ClientInfo {
+                        id: id.parse().or_invalid_frame()?,
+                        addr,
+                        nick,
+                        room,
+                        idle_secs: idle_secs.parse().or_invalid_frame()?,
+                    })))
+                }
+                "stat" => {
+                    let [name, value] = destructure_args(args)?;
+                    Ok(Some(AdminResponse::Stat { name, value }))
+                }
+                "ok" => {
+                    let [message] = destructure_args(args)?;
+                    Ok(Some(AdminResponse::Ok(message)))
+                }
+                "error" => {
+                    let [message] = destructure_args(args)?;
+                    Ok(Some(AdminResponse::Error(message)))
+                }
+                _ => Err(Error::InvalidFrame),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Encoder<AdminResponse> for AdminResponseCodec {
+    type Error = Error;
+
+    fn encode(&mut self, response: AdminResponse, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        use AdminResponse::*;
+        match response {
+            Client(info) => encode_frame(
+                b"client",
+                [
+                    &info.id.to_string(),
+                    &info.addr,
+                    &info.nick,
+                    &info.room,
+                    &info.idle_secs.to_string(),
+                ],
+                dst,
+            ),
+            Stat { name, value } => encode_frame(b"stat", [&name, &value], dst),
+            Ok(message) => encode_frame(b"ok", [&message], dst),
+            Error(message) => encode_frame(b"error", [&message], dst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AdminRequest, AdminRequestCodec, AdminResponse, AdminResponseCodec, ClientInfo};
+    use tokio_util::{
+        bytes::BytesMut,
+        codec::{Decoder, Encoder},
+    };
+
+    #[test]
+    fn test_admin_codecs() {
+        let requests = vec![
+            AdminRequest::List,
+            AdminRequest::kick("Mole Man", "Stop digging"),
+            AdminRequest::notice("", "Restarting soon"),
+            AdminRequest::Reload,
+            AdminRequest::Stats,
+        ];
+        for request in requests {
+            let mut buffer = BytesMut::new();
+            AdminRequestCodec::default()
+                .encode(request.clone(), &mut buffer)
+                .unwrap();
+            let decoded = AdminRequestCodec::default().decode(&mut buffer).unwrap();
+            assert_eq!(decoded, Some(request));
+        }
+
+        let responses = vec![
+            AdminResponse::Client(ClientInfo {
+                id: 7,
+                addr: String::from("127.0.0.1:51234"),
+                nick: String::from("Sue Storm"),
+                room: String::from("lobby"),
+                idle_secs: 42,
+            }),
+            AdminResponse::stat("connections", 3),
+            AdminResponse::ok("done"),
+            AdminResponse::error("no such user"),
+        ];
+        for response in responses {
+            let mut buffer = BytesMut::new();
+            AdminResponseCodec::default()
+                .encode(response.clone(), &mut buffer)
+                .unwrap();
+            let decoded = AdminResponseCodec::default().decode(&mut buffer).unwrap();
+            assert_eq!(decoded, Some(response));
+        }
+    }
+}
@@ -1,8 +1,8 @@
 /// Codecs for simple chat protocol
 use crate::{
-    model::{ReceivedMessage, SentMessage},
+    model::{Reaction, ReceivedMessage, SentMessage},
     util::ResultExt,
-    Error,
+    Error, MessageId,
 };
 use base64::{
     engine::general_purpose::STANDARD as B64_STANDARD, read::DecoderReader, write::EncoderWriter,
@@ -14,20 +14,171 @@ use tokio_util::{
 };
 
 // 640k ought to be enough for anyone
-const MAX_LENGTH: usize = 1024 * 640;
+pub const MAX_LENGTH: usize = 1024 * 640;
 
 /// Messages sent from client to server
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub enum ClientFrame {
+    Join {
+        nick: String,
+        room: String,
+        /// Required when joining as a registered nickname
+        password: Option<String>,
+    },
     Send(SentMessage),
+    /// Replaces the text of one of the sender's own messages
+    Edit {
+        id: MessageId,
+        text: String,
+    },
+    /// Removes a message, either the sender's own or, for moderators, anyone's
+    Delete {
+        id: MessageId,
+    },
+    /// Asks for every message in the thread started by `root`
+    Thread {
+        root: MessageId,
+    },
+    /// Adds a reaction to a message, or removes it if the sender already
+    /// reacted with the same emoji
+    React {
+        id: MessageId,
+        emoji: String,
+    },
+    /// The sender started or stopped typing a message
+    Typing {
+        active: bool,
+    },
+    /// The sender has seen every message up to `id` in their room
+    Seen {
+        id: MessageId,
+    },
+    /// A private message to one user, wherever they are
+    Direct {
+        to: String,
+        text: String,
+    },
+    Kick {
+        nick: String,
+        reason: String,
+    },
+    Mute {
+        nick: String,
+        secs: u64,
+    },
+    Ban {
+        target: String,
+        reason: String,
+    },
+    Unban {
+        target: String,
+    },
+    SetRole {
+        nick: String,
+        role: String,
+    },
     Leave,
 }
 
 impl ClientFrame {
+    pub fn join(nick: impl Into<String>, room: impl Into<String>) -> Self {
+        Self::Join {
+            nick: nick.into(),
+            room: room.into(),
+            password: None,
+        }
+    }
+
+    pub fn join_with_password(
+        nick: impl Into<String>,
+        room: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self::Join {
+            nick: nick.into(),
+            room: room.into(),
+            password: Some(password.into()),
+        }
+    }
+
     pub fn send(msg: impl Into<SentMessage>) -> Self {
         Self::Send(msg.into())
     }
 
+    pub fn edit(id: MessageId, text: impl Into<String>) -> Self {
+        Self::Edit {
+            id,
+            text: text.into(),
+        }
+    }
+
+    pub fn delete(id: MessageId) -> Self {
+        Self::Delete { id }
+    }
+
+    pub fn thread(root: MessageId) -> Self {
+        Self::Thread { root }
+    }
+
+    /// Toggles a reaction, given as an emoji or a shortcode like `:tada:`
+    pub fn react(id: MessageId, emoji: impl Into<String>) -> Self {
+        Self::React {
+            id,
+            emoji: emoji.into(),
+        }
+    }
+
+    pub fn typing(active: bool) -> Self {
+        Self::Typing { active }
+    }
+
+    pub fn seen(id: MessageId) -> Self {
+        Self::Seen { id }
+    }
+
+    pub fn direct(to: impl Into<String>, text: impl Into<String>) -> Self {
+        Self::Direct {
+            to: to.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn kick(nick: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Kick {
+            nick: nick.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Mutes `nick` for `secs` seconds, or lifts a mute when `secs` is zero
+    pub fn mute(nick: impl Into<String>, secs: u64) -> Self {
+        Self::Mute {
+            nick: nick.into(),
+            secs,
+        }
+    }
+
+    /// Bans `target`, which is either a nickname or an address/CIDR
+    pub fn ban(target: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Ban {
+            target: target.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unban(target: impl Into<String>) -> Self {
+        Self::Unban {
+            target: target.into(),
+        }
+    }
+
+    pub fn set_role(nick: impl Into<String>, role: impl Into<String>) -> Self {
+        Self::SetRole {
+            nick: nick.into(),
+            role: role.into(),
+        }
+    }
+
     pub fn leave() -> Self {
         Self::Leave
     }
@@ -39,14 +190,21 @@ pub struct ClientFrameCodec {
     inner: LinesCodec,
 }
 
-impl Default for ClientFrameCodec {
-    fn default() -> Self {
+impl ClientFrameCodec {
+    /// Creates a codec that rejects frames longer than `max_length` bytes
+    pub fn new_with_max_length(max_length: usize) -> Self {
         Self {
-            inner: LinesCodec::new_with_max_length(MAX_LENGTH),
+            inner: LinesCodec::new_with_max_length(max_length),
         }
     }
 }
 
+impl Default for ClientFrameCodec {
+    fn default() -> Self {
+        Self::new_with_max_length(MAX_LENGTH)
+    }
+}
+
 impl Decoder for ClientFrameCodec {
     type Item = ClientFrame;
     type Error = Error;
@@ -54,9 +212,91 @@ impl Decoder for ClientFrameCodec {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         if let Some((verb, args)) = decode_frame(src, &mut self.inner)? {
             match verb.as_str() {
-                "send" => {
-                    let [author, text] = destructure_args(args)?;
-                    Ok(Some(ClientFrame::Send(SentMessage { author, text })))
+                "join" if args.len() == 3 => {
+                    let [nick, room, password] = destructure_args(args)?;
+                    let password = Some(password);
+                    Ok(Some(ClientFrame::Join {
+                        nick,
+                        room,
+                        password,
+                    }))
+                }
+                "join" => {
+                    let [nick, room] = destructure_args(args)?;
+                    let password = None;
+                    Ok(Some(ClientFrame::Join {
+                        nick,
+                        room,
+                        password,
+                    }))
+                }
+                "send" if (2..=5).contains(&args.len()) => {
+                    // The reply and thread IDs and nonce may be left off
+                    let mut args = args;
+                    args.resize(5, String::new());
+                    let [author, text, reply_to, thread, nonce] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::Send(SentMessage {
+                        author,
+                        text,
+                        reply_to: decode_id(&reply_to)?,
+                        thread: decode_id(&thread)?,
+                        nonce: Some(nonce).filter(|nonce| !nonce.is_empty()),
+                    })))
+                }
+                "edit" => {
+                    let [id, text] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::Edit { id, text }))
+                }
+                "delete" => {
+                    let [id] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::Delete { id }))
+                }
+                "thread" => {
+                    let [root] = destructure_args(args)?;
+                    let root = root.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::Thread { root }))
+                }
+                "react" => {
+                    let [id, emoji] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::React { id, emoji }))
+                }
+                "typing" => {
+                    let [state] = destructure_args(args)?;
+                    let active = decode_typing(&state)?;
+                    Ok(Some(ClientFrame::Typing { active }))
+                }
+                "seen" => {
+                    let [id] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::Seen { id }))
+                }
+                "dm" => {
+                    let [to, text] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::Direct { to, text }))
+                }
+                "kick" => {
+                    let [nick, reason] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::Kick { nick, reason }))
+                }
+                "mute" => {
+                    let [nick, secs] = destructure_args(args)?;
+                    let secs = secs.parse().or_invalid_frame()?;
+                    Ok(Some(ClientFrame::Mute { nick, secs }))
+                }
+                "ban" => {
+                    let [target, reason] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::Ban { target, reason }))
+                }
+                "unban" => {
+                    let [target] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::Unban { target }))
+                }
+                "role" => {
+                    let [nick, role] = destructure_args(args)?;
+                    Ok(Some(ClientFrame::SetRole { nick, role }))
                 }
                 "leave" => Ok(Some(ClientFrame::Leave)),
                 _ => Err(Error::InvalidFrame),
@@ -73,7 +313,52 @@ impl Encoder<ClientFrame> for ClientFrameCodec {
     fn encode(&mut self, frame: ClientFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
         use ClientFrame::*;
         match frame {
-            Send(msg) => encode_frame(b"send", [&msg.author, &msg.text], dst),
+            Join {
+                nick,
+                room,
+                password: None,
+            } => encode_frame(b"join", [&nick, &room], dst),
+            Join {
+                nick,
+                room,
+                password: Some(password),
+            } => encode_frame(b"join", [&nick, &room, &password], dst),
+            Send(SentMessage {
+                author,
+                text,
+                reply_to: None,
+                thread: None,
+                nonce: None,
+            }) => encode_frame(b"send", [&author, &text], dst),
+            Send(SentMessage {
+                author,
+                text,
+                reply_to,
+                thread,
+                nonce,
+            }) => encode_frame(
+                b"send",
+                [
+                    &author,
+                    &text,
+                    &encode_id(reply_to),
+                    &encode_id(thread),
+                    nonce.as_deref().unwrap_or_default(),
+                ],
+                dst,
+            ),
+            Edit { id, text } => encode_frame(b"edit", [&id.to_string(), &text], dst),
+            Delete { id } => encode_frame(b"delete", [&id.to_string()], dst),
+            Thread { root } => encode_frame(b"thread", [&root.to_string()], dst),
+            React { id, emoji } => encode_frame(b"react", [&id.to_string(), &emoji], dst),
+            Typing { active } => encode_frame(b"typing", [encode_typing(active)], dst),
+            Seen { id } => encode_frame(b"seen", [&id.to_string()], dst),
+            Direct { to, text } => encode_frame(b"dm", [&to, &text], dst),
+            Kick { nick, reason } => encode_frame(b"kick", [&nick, &reason], dst),
+            Mute { nick, secs } => encode_frame(b"mute", [&nick, &secs.to_string()], dst),
+            Ban { target, reason } => encode_frame(b"ban", [&target, &reason], dst),
+            Unban { target } => encode_frame(b"unban", [&target], dst),
+            SetRole { nick, role } => encode_frame(b"role", [&nick, &role], dst),
             Leave => encode_frame(b"leave", [], dst),
         }
     }
@@ -83,12 +368,183 @@ impl Encoder<ClientFrame> for ClientFrameCodec {
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub enum ServerFrame {
     Receive(ReceivedMessage),
+    /// The sender's message with this nonce was stored under `id`
+    Ack {
+        nonce: String,
+        id: MessageId,
+        ts: String,
+    },
+    /// The sender's message with this nonce was refused and won't be relayed
+    Rejected {
+        nonce: String,
+        reason: String,
+    },
+    /// A message's text was replaced by its author
+    Edited {
+        id: MessageId,
+        text: String,
+        ts: String,
+    },
+    /// A message was removed and should no longer be shown
+    Deleted {
+        id: MessageId,
+    },
+    /// All the reactions a message now has, sent whenever they change and
+    /// after the message itself when replaying history
+    Reactions {
+        id: MessageId,
+        reactions: Vec<Reaction>,
+    },
+    /// Someone else in the room started or stopped typing
+    Typing {
+        nick: String,
+        active: bool,
+    },
+    /// The last message the client's user had seen in the room they joined,
+    /// sent after replaying history
+    ReadMarker {
+        id: MessageId,
+    },
+    /// Someone in the room has seen every message up to `id`
+    Seen {
+        nick: String,
+        id: MessageId,
+    },
+    /// A private message, sent to its recipient and back to its sender
+    Direct {
+        from: String,
+        to: String,
+        text: String,
+        ts: String,
+    },
+    /// A message in another room mentioning the client's user, sent while
+    /// they were offline
+    Mentioned {
+        room: String,
+        id: MessageId,
+        author: String,
+        text: String,
+        ts: String,
+    },
+    /// How many private messages and mentions arrived while the client's user
+    /// was offline, sent before them when logging in
+    Offline {
+        direct: usize,
+        mentions: usize,
+    },
+    Notice(String),
+    Motd(String),
+    Error(String),
+    /// The server is closing the connection on purpose, such as after a kick,
+    /// and reconnecting would only be turned away again
+    Closed(String),
+    /// A verb this version doesn't know, from a newer server, which clients
+    /// can skip. Decoding errors end the stream, so this can't be one.
+    Unknown(String),
 }
 
 impl ServerFrame {
     pub fn receive(msg: impl Into<ReceivedMessage>) -> Self {
         Self::Receive(msg.into())
     }
+
+    pub fn ack(nonce: impl Into<String>, id: MessageId, ts: impl Into<String>) -> Self {
+        Self::Ack {
+            nonce: nonce.into(),
+            id,
+            ts: ts.into(),
+        }
+    }
+
+    pub fn rejected(nonce: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Rejected {
+            nonce: nonce.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn edited(id: MessageId, text: impl Into<String>, ts: impl Into<String>) -> Self {
+        Self::Edited {
+            id,
+            text: text.into(),
+            ts: ts.into(),
+        }
+    }
+
+    pub fn deleted(id: MessageId) -> Self {
+        Self::Deleted { id }
+    }
+
+    pub fn reactions(id: MessageId, reactions: impl Into<Vec<Reaction>>) -> Self {
+        Self::Reactions {
+            id,
+            reactions: reactions.into(),
+        }
+    }
+
+    pub fn typing(nick: impl Into<String>, active: bool) -> Self {
+        Self::Typing {
+            nick: nick.into(),
+            active,
+        }
+    }
+
+    pub fn read_marker(id: MessageId) -> Self {
+        Self::ReadMarker { id }
+    }
+
+    pub fn seen(nick: impl Into<String>, id: MessageId) -> Self {
+        Self::Seen {
+            nick: nick.into(),
+            id,
+        }
+    }
+
+    pub fn direct(
+        from: impl Into<String>,
+        to: impl Into<String>,
+        text: impl Into<String>,
+        ts: impl Into<String>,
+    ) -> Self {
+        Self::Direct {
+            from: from.into(),
+            to: to.into(),
+            text: text.into(),
+            ts: ts.into(),
+        }
+    }
+
+    pub fn mentioned(room: impl Into<String>, message: ReceivedMessage) -> Self {
+        Self::Mentioned {
+            room: room.into(),
+            id: message.id,
+            author: message.author,
+            text: message.text,
+            ts: message.ts,
+        }
+    }
+
+    pub fn offline(direct: usize, mentions: usize) -> Self {
+        Self::Offline { direct, mentions }
+    }
+
+    /// Announcement from the server rather than another user
+    pub fn notice(text: impl Into<String>) -> Self {
+        Self::Notice(text.into())
+    }
+
+    /// Message of the day, sent once after joining
+    pub fn motd(text: impl Into<String>) -> Self {
+        Self::Motd(text.into())
+    }
+
+    pub fn error(reason: impl Into<String>) -> Self {
+        Self::Error(reason.into())
+    }
+
+    pub fn closed(reason: impl Into<String>) -> Self {
+        Self::Closed(reason.into())
+    }
 }
 
 /// Codec for server frames
@@ -97,14 +553,21 @@ pub struct ServerFrameCodec {
     inner: LinesCodec,
 }
 
-impl Default for ServerFrameCodec {
-    fn default() -> Self {
+impl ServerFrameCodec {
+    /// Creates a codec that rejects frames longer than `max_length` bytes
+    pub fn new_with_max_length(max_length: usize) -> Self {
         Self {
-            inner: LinesCodec::new_with_max_length(MAX_LENGTH),
+            inner: LinesCodec::new_with_max_length(max_length),
         }
     }
 }
 
+impl Default for ServerFrameCodec {
+    fn default() -> Self {
+        Self::new_with_max_length(MAX_LENGTH)
+    }
+}
+
 impl Decoder for ServerFrameCodec {
     type Item = ServerFrame;
     type Error = Error;
@@ -113,14 +576,109 @@ impl Decoder for ServerFrameCodec {
         if let Some((verb, args)) = decode_frame(src, &mut self.inner)? {
             match verb.as_str() {
                 "receive" => {
-                    let [author, text, ts] = destructure_args(args)?;
+                    let [author, text, ts, id, edited, reply_to, thread] = destructure_args(args)?;
                     Ok(Some(ServerFrame::Receive(ReceivedMessage {
+                        id: id.parse().or_invalid_frame()?,
                         author,
                         text,
                         ts,
+                        edited: Some(edited).filter(|edited| !edited.is_empty()),
+                        reply_to: decode_id(&reply_to)?,
+                        thread: decode_id(&thread)?,
                     })))
                 }
-                _ => Err(Error::InvalidFrame),
+                "ack" => {
+                    let [nonce, id, ts] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::Ack { nonce, id, ts }))
+                }
+                "rejected" => {
+                    let [nonce, reason] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Rejected { nonce, reason }))
+                }
+                "edited" => {
+                    let [id, text, ts] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::Edited { id, text, ts }))
+                }
+                "deleted" => {
+                    let [id] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::Deleted { id }))
+                }
+                "reactions" if args.len() % 2 == 1 => {
+                    // The ID followed by an emoji and nick for every reaction
+                    let mut args = args.into_iter();
+                    let id = args.next().or_invalid_frame()?.parse().or_invalid_frame()?;
+                    let mut reactions: Vec<Reaction> = Vec::new();
+                    while let (Some(emoji), Some(nick)) = (args.next(), args.next()) {
+                        match reactions
+                            .iter_mut()
+                            .find(|reaction| reaction.emoji == emoji)
+                        {
+                            Some(reaction) => reaction.nicks.push(nick),
+                            None => reactions.push(Reaction {
+                                emoji,
+                                nicks: vec![nick],
+                            }),
+                        }
+                    }
+                    Ok(Some(ServerFrame::Reactions { id, reactions }))
+                }
+                "typing" => {
+                    let [nick, state] = destructure_args(args)?;
+                    let active = decode_typing(&state)?;
+                    Ok(Some(ServerFrame::Typing { nick, active }))
+                }
+                "read" => {
+                    let [id] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::ReadMarker { id }))
+                }
+                "seen" => {
+                    let [nick, id] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::Seen { nick, id }))
+                }
+                "dm" => {
+                    let [from, to, text, ts] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Direct { from, to, text, ts }))
+                }
+                "mentioned" => {
+                    let [room, id, author, text, ts] = destructure_args(args)?;
+                    let id = id.parse().or_invalid_frame()?;
+                    Ok(Some(ServerFrame::Mentioned {
+                        room,
+                        id,
+                        author,
+                        text,
+                        ts,
+                    }))
+                }
+                "offline" => {
+                    let [direct, mentions] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Offline {
+                        direct: direct.parse().or_invalid_frame()?,
+                        mentions: mentions.parse().or_invalid_frame()?,
+                    }))
+                }
+                "notice" => {
+                    let [text] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Notice(text)))
+                }
+                "motd" => {
+                    let [text] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Motd(text)))
+                }
+                "error" => {
+                    let [reason] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Error(reason)))
+                }
+                "closed" => {
+                    let [reason] = destructure_args(args)?;
+                    Ok(Some(ServerFrame::Closed(reason)))
+                }
+                _ => Ok(Some(ServerFrame::Unknown(verb))),
             }
         } else {
             Ok(None)
@@ -134,18 +692,71 @@ impl Encoder<ServerFrame> for ServerFrameCodec {
     fn encode(&mut self, frame: ServerFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
         use ServerFrame::*;
         match frame {
-            Receive(msg) => encode_frame(b"receive", [&msg.author, &msg.text, &msg.ts], dst),
+            Receive(msg) => encode_frame(
+                b"receive",
+                [
+                    &msg.author,
+                    &msg.text,
+                    &msg.ts,
+                    &msg.id.to_string(),
+                    msg.edited.as_deref().unwrap_or_default(),
+                    &encode_id(msg.reply_to),
+                    &encode_id(msg.thread),
+                ],
+                dst,
+            ),
+            Ack { nonce, id, ts } => encode_frame(b"ack", [&nonce, &id.to_string(), &ts], dst),
+            Rejected { nonce, reason } => encode_frame(b"rejected", [&nonce, &reason], dst),
+            Edited { id, text, ts } => encode_frame(b"edited", [&id.to_string(), &text, &ts], dst),
+            Deleted { id } => encode_frame(b"deleted", [&id.to_string()], dst),
+            Reactions { id, reactions } => {
+                let id = id.to_string();
+                let mut args = vec![id.as_str()];
+                for reaction in &reactions {
+                    for nick in &reaction.nicks {
+                        args.extend([reaction.emoji.as_str(), nick.as_str()]);
+                    }
+                }
+                encode_frame_slice(b"reactions", &args, dst)
+            }
+            Typing { nick, active } => encode_frame(b"typing", [&nick, encode_typing(active)], dst),
+            ReadMarker { id } => encode_frame(b"read", [&id.to_string()], dst),
+            Seen { nick, id } => encode_frame(b"seen", [&nick, &id.to_string()], dst),
+            Direct { from, to, text, ts } => encode_frame(b"dm", [&from, &to, &text, &ts], dst),
+            Mentioned {
+                room,
+                id,
+                author,
+                text,
+                ts,
+            } => encode_frame(
+                b"mentioned",
+                [&room, &id.to_string(), &author, &text, &ts],
+                dst,
+            ),
+            Offline { direct, mentions } => encode_frame(
+                b"offline",
+                [&direct.to_string(), &mentions.to_string()],
+                dst,
+            ),
+            Notice(text) => encode_frame(b"notice", [&text], dst),
+            Motd(text) => encode_frame(b"motd", [&text], dst),
+            Error(reason) => encode_frame(b"error", [&reason], dst),
+            Closed(reason) => encode_frame(b"closed", [&reason], dst),
+            Unknown(verb) => encode_frame(verb.as_bytes(), [], dst),
         }
     }
 }
 
 // Common logic for decoding frames
-fn decode_frame(
+pub(crate) fn decode_frame(
     src: &mut BytesMut,
     frame_decoder: &mut LinesCodec,
 ) -> Result<Option<(String, Vec<String>)>, Error> {
     if let Some(frame) = frame_decoder.decode(src)? {
-        let mut split = frame.trim().split(' ');
+        // Only leading whitespace can be trimmed, since an empty final
+        // argument encodes to nothing after its separating space
+        let mut split = frame.trim_start().split(' ');
         let verb = split.next().map(String::from).ok_or(Error::InvalidFrame)?;
         let args = split
             .map(|value| {
@@ -160,10 +771,19 @@ fn decode_frame(
 }
 
 // Common logic for encoding frames
-fn encode_frame<const N: usize>(
+pub(crate) fn encode_frame<const N: usize>(
     verb: &[u8],
     args: [&str; N],
     dst: &mut BytesMut,
+) -> Result<(), Error> {
+    encode_frame_slice(verb, &args, dst)
+}
+
+// Encodes a frame whose number of arguments varies
+pub(crate) fn encode_frame_slice(
+    verb: &[u8],
+    args: &[&str],
+    dst: &mut BytesMut,
 ) -> Result<(), Error> {
     // Reserve enough space for full encoding to avoid reallocating
     dst.reserve(
@@ -189,14 +809,42 @@ fn encode_frame<const N: usize>(
 // with `try_into`. This lets us write ergonomic code like
 // `let [a, b] = destructure_args(some_vec)?` that will return an error if there
 // aren't the right number of arguments.
-fn destructure_args<const N: usize>(args: Vec<String>) -> Result<[String; N], Error> {
+pub(crate) fn destructure_args<const N: usize>(args: Vec<String>) -> Result<[String; N], Error> {
     args.try_into().or_invalid_frame()
 }
 
+// Optional message IDs are sent as an empty argument when missing
+fn encode_id(id: Option<MessageId>) -> String {
+    id.map(|id| id.to_string()).unwrap_or_default()
+}
+
+fn encode_typing(active: bool) -> &'static str {
+    if active {
+        "start"
+    } else {
+        "stop"
+    }
+}
+
+fn decode_typing(arg: &str) -> Result<bool, Error> {
+    match arg {
+        "start" => Ok(true),
+        "stop" => Ok(false),
+        _ => Err(Error::InvalidFrame),
+    }
+}
+
+fn decode_id(arg: &str) -> Result<Option<MessageId>, Error> {
+    match arg {
+        "" => Ok(None),
+        id => Ok(Some(id.parse().or_invalid_frame()?)),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::{ClientFrame, ClientFrameCodec, ServerFrame, ServerFrameCodec};
-    use crate::{Error, ReceivedMessage, SentMessage};
+    use crate::{Error, Reaction, ReceivedMessage, SentMessage};
     use tokio_util::{
         bytes::BytesMut,
         codec::{Decoder, Encoder},
@@ -225,10 +873,82 @@ mod test {
     fn test_client_codec() {
         #[rustfmt::skip]
         let tests = vec![
+            (
+                ClientFrame::join("The Thing", "Baxter Building"),
+                "join VGhlIFRoaW5n QmF4dGVyIEJ1aWxkaW5n\n"
+            ),
+            (
+                ClientFrame::join_with_password("The Thing", "Baxter Building", "Alicia"),
+                "join VGhlIFRoaW5n QmF4dGVyIEJ1aWxkaW5n QWxpY2lh\n"
+            ),
             (
                 ClientFrame::send(SentMessage::new("The Thing", "It's Clobbering Time")),
                 "send VGhlIFRoaW5n SXQncyBDbG9iYmVyaW5nIFRpbWU=\n"
             ),
+            (
+                ClientFrame::send(SentMessage::new("The Thing", "Again").replying_to(42)),
+                "send VGhlIFRoaW5n QWdhaW4= NDI=  \n"
+            ),
+            (
+                ClientFrame::send(SentMessage::new("The Thing", "Yancy Street").in_thread(42)),
+                "send VGhlIFRoaW5n WWFuY3kgU3RyZWV0  NDI= \n"
+            ),
+            (
+                ClientFrame::send(SentMessage::new("The Thing", "Aunt Petunia").with_nonce("n1")),
+                "send VGhlIFRoaW5n QXVudCBQZXR1bmlh   bjE=\n"
+            ),
+            (
+                ClientFrame::thread(42),
+                "thread NDI=\n"
+            ),
+            (
+                ClientFrame::edit(42, "It's still Clobbering Time"),
+                "edit NDI= SXQncyBzdGlsbCBDbG9iYmVyaW5nIFRpbWU=\n"
+            ),
+            (
+                ClientFrame::delete(42),
+                "delete NDI=\n"
+            ),
+            (
+                ClientFrame::react(42, ":tada:"),
+                "react NDI= OnRhZGE6\n"
+            ),
+            (
+                ClientFrame::typing(true),
+                "typing c3RhcnQ=\n"
+            ),
+            (
+                ClientFrame::seen(42),
+                "seen NDI=\n"
+            ),
+            (
+                ClientFrame::direct("Sue Storm", "Are you there?"),
+                "dm U3VlIFN0b3Jt QXJlIHlvdSB0aGVyZT8=\n"
+            ),
+            (
+                ClientFrame::kick("Mole Man", "Stop digging"),
+                "kick TW9sZSBNYW4= U3RvcCBkaWdnaW5n\n"
+            ),
+            (
+                ClientFrame::kick("Mole Man", ""),
+                "kick TW9sZSBNYW4= \n"
+            ),
+            (
+                ClientFrame::mute("Mole Man", 600),
+                "mute TW9sZSBNYW4= NjAw\n"
+            ),
+            (
+                ClientFrame::ban("10.0.0.0/8", "Trespassing"),
+                "ban MTAuMC4wLjAvOA== VHJlc3Bhc3Npbmc=\n"
+            ),
+            (
+                ClientFrame::unban("Mole Man"),
+                "unban TW9sZSBNYW4=\n"
+            ),
+            (
+                ClientFrame::set_role("The Thing", "moderator"),
+                "role VGhlIFRoaW5n bW9kZXJhdG9y\n"
+            ),
             (
                 ClientFrame::leave(),
                 "leave\n"
@@ -249,8 +969,83 @@ mod test {
         #[rustfmt::skip]
         let tests = vec![
             (
-                ServerFrame::receive(ReceivedMessage::new("Reed Richards", "I'm really smart", TS)),
-                "receive UmVlZCBSaWNoYXJkcw== SSdtIHJlYWxseSBzbWFydA== MjAwMC0wMS0wMVQwMDowMDowMFo=\n"
+                ServerFrame::receive(ReceivedMessage::new(42, "Reed Richards", "I'm really smart", TS)),
+                "receive UmVlZCBSaWNoYXJkcw== SSdtIHJlYWxseSBzbWFydA== MjAwMC0wMS0wMVQwMDowMDowMFo= NDI=   \n"
+            ),
+            (
+                ServerFrame::receive(ReceivedMessage {
+                    reply_to: Some(42),
+                    thread: Some(40),
+                    ..ReceivedMessage::new(43, "Sue Storm", "Sure you are", TS)
+                }),
+                "receive U3VlIFN0b3Jt U3VyZSB5b3UgYXJl MjAwMC0wMS0wMVQwMDowMDowMFo= NDM=  NDI= NDA=\n"
+            ),
+            (
+                ServerFrame::ack("n1", 42, TS),
+                "ack bjE= NDI= MjAwMC0wMS0wMVQwMDowMDowMFo=\n"
+            ),
+            (
+                ServerFrame::rejected("n1", "you are muted"),
+                "rejected bjE= eW91IGFyZSBtdXRlZA==\n"
+            ),
+            (
+                ServerFrame::edited(42, "I'm really, really smart", "2000-01-01T00:05:00Z"),
+                "edited NDI= SSdtIHJlYWxseSwgcmVhbGx5IHNtYXJ0 MjAwMC0wMS0wMVQwMDowNTowMFo=\n"
+            ),
+            (
+                ServerFrame::deleted(42),
+                "deleted NDI=\n"
+            ),
+            (
+                ServerFrame::reactions(42, [
+                    Reaction { emoji: String::from("👍"), nicks: vec![String::from("Sue"), String::from("Ben")] },
+                    Reaction { emoji: String::from("🎉"), nicks: vec![String::from("Sue")] },
+                ]),
+                "reactions NDI= 8J+RjQ== U3Vl 8J+RjQ== QmVu 8J+OiQ== U3Vl\n"
+            ),
+            (
+                ServerFrame::reactions(42, []),
+                "reactions NDI=\n"
+            ),
+            (
+                ServerFrame::typing("Sue Storm", false),
+                "typing U3VlIFN0b3Jt c3RvcA==\n"
+            ),
+            (
+                ServerFrame::read_marker(42),
+                "read NDI=\n"
+            ),
+            (
+                ServerFrame::seen("Sue Storm", 42),
+                "seen U3VlIFN0b3Jt NDI=\n"
+            ),
+            (
+                ServerFrame::direct("Reed", "Sue Storm", "Are you there?", TS),
+                "dm UmVlZA== U3VlIFN0b3Jt QXJlIHlvdSB0aGVyZT8= MjAwMC0wMS0wMVQwMDowMDowMFo=\n"
+            ),
+            (
+                ServerFrame::mentioned("lab", ReceivedMessage::new(42, "Reed", "@Sue lunch?", TS)),
+                "mentioned bGFi NDI= UmVlZA== QFN1ZSBsdW5jaD8= MjAwMC0wMS0wMVQwMDowMDowMFo=\n"
+            ),
+            (
+                ServerFrame::offline(2, 3),
+                "offline Mg== Mw==\n"
+            ),
+            (
+                ServerFrame::notice("Mole Man was kicked"),
+                "notice TW9sZSBNYW4gd2FzIGtpY2tlZA==\n"
+            ),
+            (
+                ServerFrame::motd("Welcome to the Baxter Building"),
+                "motd V2VsY29tZSB0byB0aGUgQmF4dGVyIEJ1aWxkaW5n\n"
+            ),
+            (
+                ServerFrame::error("banned"),
+                "error YmFubmVk\n"
+            ),
+            (
+                ServerFrame::closed("banned"),
+                "closed YmFubmVk\n"
             ),
         ];
         for test in tests {
@@ -261,4 +1056,14 @@ mod test {
             assert_eq!(decoded, item);
         }
     }
+
+    #[test]
+    fn test_unknown_server_verbs_are_skippable() {
+        let mut codec = ServerFrameCodec::default();
+        let mut buffer = BytesMut::from("sparkle Zm9v YmFy\nnotice aGk=\n");
+        let unknown = codec.decode(&mut buffer).unwrap();
+        assert_eq!(unknown, Some(ServerFrame::Unknown(String::from("sparkle"))));
+        let next = codec.decode(&mut buffer).unwrap();
+        assert_eq!(next, Some(ServerFrame::notice("hi")));
+    }
 }
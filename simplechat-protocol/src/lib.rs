@@ -10,12 +10,17 @@
 /// Where `verb` is a simple ASCII string such as `send` or `receive`.
 use thiserror::Error;
 
+mod admin;
 mod codec;
 mod model;
 mod util;
 
-pub use codec::{ClientFrame, ClientFrameCodec, ServerFrame, ServerFrameCodec};
-pub use model::{ReceivedMessage, SentMessage};
+pub use admin::{AdminRequest, AdminRequestCodec, AdminResponse, AdminResponseCodec, ClientInfo};
+pub use codec::{ClientFrame, ClientFrameCodec, ServerFrame, ServerFrameCodec, MAX_LENGTH};
+pub use model::{mentions, nick_key, same_nick, Reaction, ReceivedMessage, SentMessage};
+
+/// Identifies a message, assigned by the server when it is first relayed
+pub type MessageId = u64;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -1,11 +1,18 @@
 /// Model definition for types sent/received by simple chat
-use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use crate::MessageId;
 
 /// Message as sent by client
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct SentMessage {
     pub author: String,
     pub text: String,
+    /// The message this one replies to
+    pub reply_to: Option<MessageId>,
+    /// The first message of the thread this one is posted in
+    pub thread: Option<MessageId>,
+    /// Chosen by the client to match the server's acknowledgement to this
+    /// message, and to avoid storing it twice when it is sent again
+    pub nonce: Option<String>,
 }
 
 impl SentMessage {
@@ -13,8 +20,29 @@ impl SentMessage {
         Self {
             author: author.into(),
             text: text.into(),
+            reply_to: None,
+            thread: None,
+            nonce: None,
         }
     }
+
+    /// Asks the server to acknowledge this message, quoting `nonce`
+    pub fn with_nonce(mut self, nonce: impl Into<String>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Makes this message a reply to `parent`
+    pub fn replying_to(mut self, parent: MessageId) -> Self {
+        self.reply_to = Some(parent);
+        self
+    }
+
+    /// Posts this message in the thread started by `root`
+    pub fn in_thread(mut self, root: MessageId) -> Self {
+        self.thread = Some(root);
+        self
+    }
 }
 
 impl From<(String, String)> for SentMessage {
@@ -24,30 +52,95 @@ impl From<(String, String)> for SentMessage {
     }
 }
 
-/// Message as relayed from server to other clients (includes timestamp)
+/// Message as relayed from server to clients (includes ID and timestamp)
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct ReceivedMessage {
+    pub id: MessageId,
     pub author: String,
     pub text: String,
     pub ts: String,
+    /// When the text was last changed, if it has been edited
+    pub edited: Option<String>,
+    /// The message this one replies to
+    pub reply_to: Option<MessageId>,
+    /// The first message of the thread this one was posted in
+    pub thread: Option<MessageId>,
 }
 
 impl ReceivedMessage {
-    pub fn new(author: impl Into<String>, text: impl Into<String>, ts: impl Into<String>) -> Self {
+    pub fn new(
+        id: MessageId,
+        author: impl Into<String>,
+        text: impl Into<String>,
+        ts: impl Into<String>,
+    ) -> Self {
         Self {
+            id,
             author: author.into(),
             text: text.into(),
             ts: ts.into(),
+            edited: None,
+            reply_to: None,
+            thread: None,
         }
     }
 }
 
-impl From<SentMessage> for ReceivedMessage {
-    fn from(value: SentMessage) -> Self {
-        let SentMessage { author, text } = value;
-        let ts = OffsetDateTime::now_utc()
-            .format(&Rfc3339)
-            .unwrap_or_default();
-        Self { author, text, ts }
+/// Everyone who reacted to a message with one emoji
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Reaction {
+    pub emoji: String,
+    /// In the order they reacted
+    pub nicks: Vec<String>,
+}
+
+/// Whether `text` mentions `nick` as `@nick`, ignoring case. Nicknames may
+/// contain spaces, so this looks for a particular one rather than parsing
+/// mentions out of the text.
+pub fn mentions(text: &str, nick: &str) -> bool {
+    if nick.is_empty() {
+        return false;
+    }
+    let text = text.to_lowercase();
+    let mention = format!("@{}", nick.to_lowercase());
+    text.match_indices(&mention).any(|(start, _)| {
+        // `@Sue` doesn't mention `Su`
+        let next = text[start + mention.len()..].chars().next();
+        !next.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// Whether `a` and `b` are the same nickname, which the server treats without
+/// regard to case
+pub fn same_nick(a: &str, b: &str) -> bool {
+    nick_key(a) == nick_key(b)
+}
+
+/// The form of `nick` that is the same for every way of writing it, for
+/// keeping things by nickname
+pub fn nick_key(nick: &str) -> String {
+    nick.to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mentions, nick_key, same_nick};
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("@Sue lunch?", "Sue"));
+        assert!(mentions("lunch, @sue?", "Sue"));
+        assert!(mentions("ask @The Thing", "The Thing"));
+        assert!(!mentions("@Susan lunch?", "Sue"));
+        assert!(!mentions("Sue, lunch?", "Sue"));
+        assert!(!mentions("@", ""));
+    }
+
+    #[test]
+    fn test_same_nick() {
+        assert!(same_nick("Sue", "sue"));
+        assert!(same_nick("Ångström", "ÅNGSTRÖM"));
+        assert!(!same_nick("Sue", "Susan"));
+        assert_eq!(nick_key("Sue Storm"), nick_key("SUE STORM"));
     }
 }
@@ -0,0 +1,193 @@
+/// Local admin control socket, used by `simplechat-admin`
+use crate::{
+    moderation::{self, Actor},
+    state::State,
+    ACCEPT_RETRY_DELAY,
+};
+use anyhow::{bail, Context, Result};
+use futures::{SinkExt, TryStreamExt};
+use simplechat_protocol::{
+    AdminRequest, AdminRequestCodec, AdminResponse, AdminResponseCodec, ClientInfo,
+};
+use std::{
+    fs::{self, DirBuilder},
+    io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, error};
+
+/// Binds the control socket at `path`, replacing a stale one left behind by
+/// a previous run. Only the owner of the server process may connect.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    remove_stale(path)?;
+    // Bind inside a private directory and move the socket into place once its
+    // permissions are set, so nobody else can ever connect to it
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .context("admin socket path has no file name")?;
+    let private = parent.join(format!(
+        ".{}.{}",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .with_context(|| format!("failed to create {}", private.display()))?;
+    let result = bind_in(&private, path);
+    let _ = fs::remove_dir_all(&private);
+    result
+}
+
+fn bind_in(private: &Path, path: &Path) -> Result<UnixListener> {
+    let temporary = private.join("admin.sock");
+    let listener = UnixListener::bind(&temporary)
+        .with_context(|| format!("failed to bind admin socket {}", path.display()))?;
+    fs::set_permissions(&temporary, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&temporary, path)
+        .with_context(|| format!("failed to bind admin socket {}", path.display()))?;
+    Ok(listener)
+}
+
+// Removes a socket at `path` that nothing is listening on. Anything else there
+// is left alone and reported.
+fn remove_stale(path: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("failed to check {}", path.display())),
+    };
+    if !metadata.file_type().is_socket() {
+        bail!("{} exists and is not a socket", path.display());
+    }
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => bail!("another server is listening on {}", path.display()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display())),
+        Err(e) => Err(e).with_context(|| format!("failed to check {}", path.display())),
+    }
+}
+
+/// Accepts admin connections for as long as the server runs
+pub async fn serve(listener: UnixListener, state: Arc<State>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_admin(stream, state.clone()));
+            }
+            Err(e) => {
+                error!("failed to accept admin connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn handle_admin(stream: UnixStream, state: Arc<State>) {
+    let (rx, tx) = stream.into_split();
+    let mut reader = FramedRead::new(rx, AdminRequestCodec::default());
+    let mut writer = FramedWrite::new(tx, AdminResponseCodec::default());
+    while let Ok(Some(request)) = reader.try_next().await {
+        debug!(?request, "admin request");
+        for response in respond(&state, request) {
+            if writer.send(response).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn respond(state: &State, request: AdminRequest) -> Vec<AdminResponse> {
+    match request {
+        AdminRequest::List => {
+            let mut responses: Vec<_> = state
+                .clients()
+                .into_iter()
+                .map(|(id, handle)| {
+                    AdminResponse::Client(ClientInfo {
+                        id: id as u64,
+                        addr: handle.addr.to_string(),
+                        nick: handle.nick,
+                        room: handle.room.unwrap_or_default(),
+                        idle_secs: handle.last_active.elapsed().as_secs(),
+                    })
+                })
+                .collect();
+            responses.push(AdminResponse::ok(format!("{} clients", responses.len())));
+            responses
+        }
+        AdminRequest::Kick { nick, reason } => {
+            match moderation::kick(state, &Actor::admin(), &nick, &reason) {
+                Ok(()) => vec![AdminResponse::ok(format!("kicked {}", nick))],
+                Err(e) => vec![AdminResponse::error(e.to_string())],
+            }
+        }
+        AdminRequest::Notice { room, text } => {
+            let rooms = if room.is_empty() {
+                state.rooms().into_iter().collect()
+            } else {
+                vec![room]
+            };
+            for room in &rooms {
+                state.announce(room, text.as_str());
+            }
+            vec![AdminResponse::ok(format!("sent to {} rooms", rooms.len()))]
+        }
+        AdminRequest::Reload => match state.reload_config() {
+            Ok(restart_required) if restart_required.is_empty() => {
+                vec![AdminResponse::ok("reloaded configuration")]
+            }
+            Ok(restart_required) => vec![AdminResponse::ok(format!(
+                "reloaded configuration, but changes to {} take effect after a restart",
+                restart_required.join(", ")
+            ))],
+            Err(e) => vec![AdminResponse::error(format!("{:#}", e))],
+        },
+        AdminRequest::Stats => {
+            let stats = &state.stats;
+            vec![
+                AdminResponse::stat("uptime_secs", stats.started.elapsed().as_secs()),
+                AdminResponse::stat("connections", state.clients().len()),
+                AdminResponse::stat("rooms", state.rooms().len()),
+                AdminResponse::stat("accepted", stats.accepted.load(Ordering::Relaxed)),
+                AdminResponse::stat("rejected", stats.rejected.load(Ordering::Relaxed)),
+                AdminResponse::stat("relayed", stats.relayed.load(Ordering::Relaxed)),
+                AdminResponse::ok(""),
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::bind;
+    use std::{fs, os::unix::fs::PermissionsExt};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_bind_replaces_only_stale_sockets() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("admin.sock");
+
+        let listener = bind(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(bind(&path).is_err());
+        drop(listener);
+        bind(&path).unwrap();
+
+        let file = dir.path().join("not-a-socket");
+        fs::write(&file, "keep me").unwrap();
+        assert!(bind(&file).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,315 @@
+/// Persistent list of banned addresses and nicknames
+use anyhow::{anyhow, Context, Result};
+use ipnet::IpNet;
+use simplechat_protocol::same_nick;
+use std::{
+    fmt, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::SystemTime,
+};
+use tracing::{error, info};
+
+/// A single entry in the ban list
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Ban {
+    /// Bans a single address or a whole CIDR range
+    Addr(IpNet),
+    /// Bans a nickname, compared case-insensitively
+    Nick(String),
+}
+
+impl Ban {
+    /// Interprets `target` as an address or CIDR if possible, otherwise as a
+    /// nickname
+    pub fn from_target(target: &str) -> Self {
+        let target = target.trim();
+        target
+            .parse::<IpNet>()
+            .or_else(|_| target.parse::<IpAddr>().map(IpNet::from))
+            .map(Ban::Addr)
+            .unwrap_or_else(|_| Ban::Nick(target.to_string()))
+    }
+
+    fn matches_addr(&self, addr: IpAddr) -> bool {
+        matches!(self, Ban::Addr(net) if net.contains(&canonical(addr)))
+    }
+
+    fn matches_nick(&self, nick: &str) -> bool {
+        matches!(self, Ban::Nick(banned) if same_nick(banned, nick))
+    }
+}
+
+impl FromStr for Ban {
+    type Err = anyhow::Error;
+
+    /// Parses entries of the form `ip <addr or cidr>` or `nick <nickname>`
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, value) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("expected `ip <addr>` or `nick <name>`, got {:?}", s))?;
+        let value = value.trim();
+        match kind {
+            "ip" => {
+                let net = value
+                    .parse::<IpNet>()
+                    .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+                    .with_context(|| format!("invalid address or CIDR {:?}", value))?;
+                Ok(Ban::Addr(net))
+            }
+            "nick" => Ok(Ban::Nick(value.to_string())),
+            _ => Err(anyhow!("unknown ban type {:?}", kind)),
+        }
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ban::Addr(net) => write!(f, "ip {}", net),
+            Ban::Nick(nick) => write!(f, "nick {}", nick),
+        }
+    }
+}
+
+/// The address to treat a connection from `addr` as coming from. IPv4
+/// clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses,
+/// which would otherwise slip past IPv4 bans and limits.
+pub fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// Ban list backed by a file with one entry per line
+///
+/// The file is re-read when `poll` finds its modification time has changed,
+/// so bans can be edited by hand while the server is running.
+#[derive(Debug)]
+pub struct BanList {
+    path: PathBuf,
+    bans: Vec<Ban>,
+    modified: Option<SystemTime>,
+}
+
+impl BanList {
+    /// Loads the ban list at `path`; a missing file is an empty list
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let (bans, modified) = read_bans(&path)
+            .with_context(|| format!("failed to load ban list {}", path.display()))?;
+        Ok(Self {
+            path,
+            bans,
+            modified,
+        })
+    }
+
+    /// Checks whether connections from `addr` are banned
+    pub fn is_addr_banned(&self, addr: IpAddr) -> bool {
+        self.bans.iter().any(|ban| ban.matches_addr(addr))
+    }
+
+    /// Checks whether `nick` is banned
+    pub fn is_nick_banned(&self, nick: &str) -> bool {
+        self.bans.iter().any(|ban| ban.matches_nick(nick))
+    }
+
+    /// Picks up changes made to the file since it was last read, touching the
+    /// file system without holding the lock on `list`. A file that fails to
+    /// parse is reported and the previous list is kept.
+    pub fn poll(list: &Mutex<Self>) {
+        let (path, modified) = {
+            let list = list.lock().unwrap();
+            (list.path.clone(), list.modified)
+        };
+        if modified_time(&path).ok().flatten() == modified {
+            return;
+        }
+        match read_bans(&path) {
+            Ok((bans, read_modified)) => {
+                let mut list = list.lock().unwrap();
+                // A moderator may have written the file in the meantime
+                if list.modified == modified {
+                    info!(path = %path.display(), "reloaded ban list");
+                    list.bans = bans;
+                    list.modified = read_modified;
+                }
+            }
+            Err(e) => error!(path = %path.display(), "failed to reload ban list: {:#}", e),
+        }
+    }
+
+    /// The file the list is kept in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a ban and writes the list back to disk
+    pub fn add(&mut self, ban: Ban) -> Result<()> {
+        self.refresh();
+        if !self.bans.contains(&ban) {
+            self.bans.push(ban);
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Removes a ban and writes the list back to disk, returning whether it
+    /// was present
+    pub fn remove(&mut self, ban: &Ban) -> Result<bool> {
+        self.refresh();
+        let before = self.bans.len();
+        self.bans.retain(|existing| match (existing, ban) {
+            (Ban::Nick(a), Ban::Nick(b)) => !same_nick(a, b),
+            _ => existing != ban,
+        });
+        let removed = self.bans.len() != before;
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let contents: String = self.bans.iter().map(|ban| format!("{}\n", ban)).collect();
+        fs::write(&self.path, contents)
+            .with_context(|| format!("failed to write ban list {}", self.path.display()))?;
+        self.modified = modified_time(&self.path)?;
+        Ok(())
+    }
+
+    // Picks up hand edits before writing the file back, so they aren't lost.
+    // A file that fails to parse is reported and the previous list is kept.
+    fn refresh(&mut self) {
+        if modified_time(&self.path).ok().flatten() == self.modified {
+            return;
+        }
+        match read_bans(&self.path) {
+            Ok((bans, modified)) => {
+                info!(path = %self.path.display(), "reloaded ban list");
+                self.bans = bans;
+                self.modified = modified;
+            }
+            Err(e) => error!(path = %self.path.display(), "failed to reload ban list: {:#}", e),
+        }
+    }
+}
+
+fn read_bans(path: &Path) -> Result<(Vec<Ban>, Option<SystemTime>)> {
+    let modified = modified_time(path)?;
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let bans = contents
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| line.parse().with_context(|| format!("line {}", n + 1)))
+        .collect::<Result<_>>()?;
+    Ok((bans, modified))
+}
+
+fn modified_time(path: &Path) -> io::Result<Option<SystemTime>> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(Some(metadata.modified()?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ban, BanList};
+    use std::net::IpAddr;
+    use tempfile::TempDir;
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let nick: Ban = "nick Doctor Doom".parse().unwrap();
+        assert_eq!(nick, Ban::Nick(String::from("Doctor Doom")));
+        assert_eq!(nick.to_string(), "nick Doctor Doom");
+        assert_eq!(
+            "ip 10.0.0.0/8".parse::<Ban>().unwrap().to_string(),
+            "ip 10.0.0.0/8"
+        );
+        assert!("host example.com".parse::<Ban>().is_err());
+        assert!("ip not-an-addr".parse::<Ban>().is_err());
+        assert!("nick".parse::<Ban>().is_err());
+    }
+
+    #[test]
+    fn test_from_target() {
+        let net: Ban = "ip 10.0.0.0/8".parse().unwrap();
+        assert_eq!(Ban::from_target("10.0.0.0/8"), net);
+        assert_eq!(
+            Ban::from_target(" 192.168.1.1 "),
+            "ip 192.168.1.1".parse().unwrap()
+        );
+        assert_eq!(
+            Ban::from_target("Doctor Doom"),
+            "nick Doctor Doom".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cidr_matching() {
+        let net: Ban = "ip 10.0.0.0/8".parse().unwrap();
+        assert!(net.matches_addr(addr("10.1.2.3")));
+        assert!(net.matches_addr(addr("10.255.255.255")));
+        assert!(!net.matches_addr(addr("11.0.0.1")));
+        let single: Ban = "ip 192.168.1.1".parse().unwrap();
+        assert!(single.matches_addr(addr("192.168.1.1")));
+        assert!(!single.matches_addr(addr("192.168.1.2")));
+        let v6: Ban = "ip 2001:db8::/32".parse().unwrap();
+        assert!(v6.matches_addr(addr("2001:db8::1")));
+        assert!(!v6.matches_addr(addr("2001:db9::1")));
+    }
+
+    #[test]
+    fn test_mapped_addresses_match_ipv4_bans() {
+        let net: Ban = "ip 10.0.0.0/8".parse().unwrap();
+        assert!(net.matches_addr(addr("::ffff:10.1.2.3")));
+        assert!(!net.matches_addr(addr("::ffff:11.0.0.1")));
+        let single: Ban = "ip 192.168.1.1".parse().unwrap();
+        assert!(single.matches_addr(addr("::ffff:192.168.1.1")));
+    }
+
+    #[test]
+    fn test_nicks_ignore_case() {
+        let nick: Ban = "nick Doctor Doom".parse().unwrap();
+        assert!(nick.matches_nick("doctor doom"));
+        assert!(!nick.matches_nick("Doctor Octopus"));
+        assert!(!nick.matches_addr(addr("10.1.2.3")));
+    }
+
+    #[test]
+    fn test_add_and_remove_survive_reload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bans.txt");
+        let mut bans = BanList::load(&path).unwrap();
+        bans.add(Ban::from_target("10.0.0.0/8")).unwrap();
+        bans.add(Ban::from_target("Doctor Doom")).unwrap();
+
+        let mut bans = BanList::load(&path).unwrap();
+        assert!(bans.is_addr_banned(addr("::ffff:10.0.0.1")));
+        assert!(bans.is_nick_banned("DOCTOR DOOM"));
+        assert!(bans.remove(&Ban::from_target("doctor doom")).unwrap());
+        assert!(!bans.remove(&Ban::from_target("doctor doom")).unwrap());
+
+        let bans = BanList::load(&path).unwrap();
+        assert!(!bans.is_nick_banned("Doctor Doom"));
+        assert!(bans.is_addr_banned(addr("10.0.0.1")));
+    }
+}
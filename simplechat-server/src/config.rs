@@ -0,0 +1,672 @@
+/// Server configuration, read from a TOML file
+///
+/// Every setting has a default, so the file only needs to mention what it
+/// changes. See `server.example.toml` for a description of each setting.
+use crate::{
+    log::{Format, Level},
+    moderation::Role,
+};
+use anyhow::{anyhow, Context, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use clap::Args;
+use serde::Deserialize;
+use simplechat_protocol::{nick_key, same_nick, MAX_LENGTH};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+    path::PathBuf,
+};
+
+/// Where the configuration comes from, so it can be read again later
+#[derive(Debug)]
+pub struct ConfigSource {
+    path: Option<PathBuf>,
+    overrides: Overrides,
+}
+
+impl ConfigSource {
+    pub fn new(path: Option<PathBuf>, overrides: Overrides) -> Self {
+        Self { path, overrides }
+    }
+
+    /// Reads the file, if any, applies the overrides and validates the result
+    pub fn load(&self) -> Result<Config> {
+        let mut config = match &self.path {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        self.overrides.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Command line flags, which override settings from the config file
+#[derive(Args, Clone, Debug, Default)]
+pub struct Overrides {
+    /// Bind to this addr (may be repeated) [default: localhost:3000]
+    #[arg(short, long)]
+    addr: Vec<String>,
+
+    /// Maximum number of simultaneous connections [default: 1024]
+    #[arg(long)]
+    max_connections: Option<usize>,
+
+    /// Maximum number of simultaneous connections from a single IP address
+    /// [default: 16]
+    #[arg(long)]
+    max_connections_per_ip: Option<usize>,
+
+    /// Directory holding messages, the ban list and moderation log
+    /// [default: .]
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Give this nickname the operator role (may be repeated)
+    #[arg(long = "operator", value_name = "NICK")]
+    operators: Vec<String>,
+
+    /// Give this nickname the moderator role (may be repeated)
+    #[arg(long = "moderator", value_name = "NICK")]
+    moderators: Vec<String>,
+
+    /// Unix socket that `simplechat-admin` connects to
+    /// [default: simplechat-admin.sock]
+    #[arg(long)]
+    admin_socket: Option<PathBuf>,
+
+    /// Serve Prometheus metrics over HTTP on this addr
+    #[arg(long)]
+    metrics_addr: Option<String>,
+
+    /// Serve health checks over HTTP on this addr
+    #[arg(long)]
+    health_addr: Option<String>,
+
+    /// Least important messages to log [default: info]
+    #[arg(long)]
+    log_level: Option<Level>,
+
+    /// How to write log messages [default: text]
+    #[arg(long)]
+    log_format: Option<Format>,
+}
+
+impl Overrides {
+    /// Overrides settings in `config` with any flags that were given
+    fn apply(&self, config: &mut Config) {
+        if !self.addr.is_empty() {
+            config.listen.addrs = self.addr.clone();
+        }
+        if let Some(max_connections) = self.max_connections {
+            config.limits.max_connections = max_connections;
+        }
+        if let Some(max_connections_per_ip) = self.max_connections_per_ip {
+            config.limits.max_connections_per_ip = max_connections_per_ip;
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.persistence.data_dir = data_dir.clone();
+        }
+        if let Some(admin_socket) = &self.admin_socket {
+            config.listen.admin_socket = admin_socket.clone();
+        }
+        if let Some(metrics_addr) = &self.metrics_addr {
+            config.listen.metrics_addr = Some(metrics_addr.clone());
+        }
+        if let Some(health_addr) = &self.health_addr {
+            config.listen.health_addr = Some(health_addr.clone());
+        }
+        if let Some(level) = self.log_level {
+            config.logging.level = level;
+        }
+        if let Some(format) = self.log_format {
+            config.logging.format = format;
+        }
+        config.auth.operators.extend(self.operators.iter().cloned());
+        config
+            .auth
+            .moderators
+            .extend(self.moderators.iter().cloned());
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub listen: ListenConfig,
+    pub limits: LimitsConfig,
+    pub persistence: PersistenceConfig,
+    pub rooms: Vec<RoomConfig>,
+    pub auth: AuthConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub name: String,
+    /// Message of the day, sent to clients after they join
+    pub motd: Option<String>,
+    /// File to read the message of the day from instead, each time it's sent
+    pub motd_file: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: String::from("simplechat"),
+            motd: None,
+            motd_file: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// The message of the day before its variables are filled in, if any
+    pub fn motd_template(&self) -> Result<Option<String>> {
+        match &self.motd_file {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                Ok(Some(text.trim_end().to_string()))
+            }
+            None => Ok(self.motd.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    /// Addresses to accept chat connections on
+    pub addrs: Vec<String>,
+    pub admin_socket: PathBuf,
+    /// Address to serve `/metrics` on, if any
+    pub metrics_addr: Option<String>,
+    /// Address to serve `/healthz` and `/readyz` on, if any
+    pub health_addr: Option<String>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            addrs: vec![String::from("localhost:3000")],
+            admin_socket: PathBuf::from("simplechat-admin.sock"),
+            metrics_addr: None,
+            health_addr: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    /// Longest frame accepted from a client, in bytes
+    pub max_frame_length: usize,
+    /// Messages buffered for slow clients before they start missing some
+    pub broadcast_capacity: usize,
+    /// How long after sending a message its author may still edit it
+    pub edit_window_secs: u64,
+    /// Largest room, in people, whose members are told who has read what
+    pub seen_by_max_members: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            max_connections_per_ip: 16,
+            max_frame_length: MAX_LENGTH,
+            broadcast_capacity: 256,
+            edit_window_secs: 15 * 60,
+            seen_by_max_members: 10,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistenceConfig {
+    /// Directory holding messages, the ban list and moderation log
+    pub data_dir: PathBuf,
+    /// Messages kept per room
+    pub history_limit: usize,
+    /// Latest messages sent to clients when they join a room
+    pub history_replay: usize,
+    /// Private messages and mentions kept for each offline registered user
+    pub offline_limit: usize,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("."),
+            history_limit: 1000,
+            history_replay: 50,
+            offline_limit: 100,
+        }
+    }
+}
+
+impl PersistenceConfig {
+    pub fn message_file(&self) -> PathBuf {
+        self.data_dir.join("messages.jsonl")
+    }
+
+    pub fn ban_file(&self) -> PathBuf {
+        self.data_dir.join("bans.txt")
+    }
+
+    pub fn moderation_log(&self) -> PathBuf {
+        self.data_dir.join("moderation.log")
+    }
+
+    pub fn read_marker_file(&self) -> PathBuf {
+        self.data_dir.join("read_markers.json")
+    }
+
+    pub fn mailbox_file(&self) -> PathBuf {
+        self.data_dir.join("mailbox.jsonl")
+    }
+}
+
+/// A room clients may join. If no rooms are configured, any room name is
+/// accepted.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RoomConfig {
+    pub name: String,
+    pub topic: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Only registered users may join
+    pub require_registration: bool,
+    pub operators: Vec<String>,
+    pub moderators: Vec<String>,
+    pub users: Vec<UserConfig>,
+}
+
+/// A registered nickname, which can only be used with its password
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub nick: String,
+    /// Argon2 hash of the password as a PHC string, as printed by
+    /// `simplechat-server --hash-password`
+    pub password_hash: String,
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl UserConfig {
+    /// Checks `password` against the stored hash. This is deliberately slow,
+    /// so call it off the async runtime.
+    pub fn check_password(&self, password: &str) -> bool {
+        PasswordHash::new(&self.password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
+/// Hashes `password` with a random salt, for `auth.users`
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: Level,
+    pub format: Format,
+    /// Levels for particular modules, overriding `level`
+    pub targets: BTreeMap<String, Level>,
+}
+
+impl Config {
+    /// Reads and parses the file at `path` without validating it
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+
+    /// Checks settings that parse but make no sense, reporting all of them
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.server.motd.is_some() && self.server.motd_file.is_some() {
+            problems.push(String::from(
+                "server.motd and server.motd_file can't both be set",
+            ));
+        } else if let Err(e) = self.server.motd_template() {
+            problems.push(format!("server.motd_file: {:#}", e));
+        }
+        if self.listen.addrs.is_empty() {
+            problems.push(String::from("listen.addrs must list at least one address"));
+        }
+        let limits = [
+            ("max_connections", self.limits.max_connections),
+            ("max_connections_per_ip", self.limits.max_connections_per_ip),
+            ("max_frame_length", self.limits.max_frame_length),
+            ("broadcast_capacity", self.limits.broadcast_capacity),
+        ];
+        for (name, value) in limits {
+            if value == 0 {
+                problems.push(format!("limits.{} must be at least 1", name));
+            }
+        }
+        let mut rooms = HashSet::new();
+        for room in &self.rooms {
+            if room.name.trim().is_empty() {
+                problems.push(String::from("rooms: room names must not be empty"));
+            } else if !rooms.insert(room.name.as_str()) {
+                problems.push(format!("rooms: {:?} is defined more than once", room.name));
+            }
+        }
+        let mut users = HashSet::new();
+        for user in &self.auth.users {
+            if user.nick.trim().is_empty() {
+                problems.push(String::from("auth.users: nicknames must not be empty"));
+            } else if !users.insert(nick_key(&user.nick)) {
+                problems.push(format!(
+                    "auth.users: {:?} is defined more than once",
+                    user.nick
+                ));
+            }
+            let argon2 = PasswordHash::new(&user.password_hash)
+                .is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"));
+            if !argon2 {
+                problems.push(format!(
+                    "auth.users: password_hash for {:?} must be an argon2 PHC string",
+                    user.nick
+                ));
+            }
+        }
+        let listed = [
+            ("operators", &self.auth.operators),
+            ("moderators", &self.auth.moderators),
+        ];
+        for (name, nicks) in listed {
+            for nick in nicks.iter().filter(|nick| self.user(nick).is_none()) {
+                problems.push(format!(
+                    "auth.{}: {:?} must be registered in auth.users",
+                    name, nick
+                ));
+            }
+        }
+        if self.auth.require_registration && self.auth.users.is_empty() {
+            problems.push(String::from(
+                "auth.require_registration is set but auth.users is empty",
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "invalid configuration:\n  {}",
+                problems.join("\n  ")
+            ))
+        }
+    }
+
+    /// Looks up a registered user by nickname
+    pub fn user(&self, nick: &str) -> Option<&UserConfig> {
+        self.auth
+            .users
+            .iter()
+            .find(|user| same_nick(&user.nick, nick))
+    }
+
+    /// Looks up a configured room, if rooms are restricted
+    pub fn room(&self, name: &str) -> Option<&RoomConfig> {
+        self.rooms.iter().find(|room| room.name == name)
+    }
+
+    /// Room joined by clients that don't ask for a particular one
+    pub fn default_room(&self) -> &str {
+        self.rooms
+            .first()
+            .map_or("lobby", |room| room.name.as_str())
+    }
+
+    /// Highest role the configuration grants `nick`. Only registered users,
+    /// whose password has been checked when they joined, have a role.
+    pub fn role(&self, nick: &str) -> Role {
+        let Some(user) = self.user(nick) else {
+            return Role::default();
+        };
+        let is = |listed: &String| same_nick(listed, nick);
+        if self.auth.operators.iter().any(is) {
+            Role::Operator
+        } else if self.auth.moderators.iter().any(is) {
+            Role::Moderator
+        } else {
+            user.role
+        }
+    }
+
+    /// Describes settings that differ from `self` in `new` and only take
+    /// effect after a restart
+    pub fn restart_required(&self, new: &Config) -> Vec<&'static str> {
+        let mut settings = Vec::new();
+        if self.listen.addrs != new.listen.addrs {
+            settings.push("listen.addrs");
+        }
+        if self.listen.admin_socket != new.listen.admin_socket {
+            settings.push("listen.admin_socket");
+        }
+        if self.listen.metrics_addr != new.listen.metrics_addr {
+            settings.push("listen.metrics_addr");
+        }
+        if self.listen.health_addr != new.listen.health_addr {
+            settings.push("listen.health_addr");
+        }
+        if self.persistence.data_dir != new.persistence.data_dir {
+            settings.push("persistence.data_dir");
+        }
+        if self.persistence.history_limit != new.persistence.history_limit {
+            settings.push("persistence.history_limit");
+        }
+        if self.persistence.history_replay != new.persistence.history_replay {
+            settings.push("persistence.history_replay");
+        }
+        if self.persistence.offline_limit != new.persistence.offline_limit {
+            settings.push("persistence.offline_limit");
+        }
+        if self.limits.broadcast_capacity != new.limits.broadcast_capacity {
+            settings.push("limits.broadcast_capacity");
+        }
+        if self.logging.format != new.logging.format {
+            settings.push("logging.format");
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_password, Config, RoomConfig, UserConfig};
+    use crate::moderation::Role;
+
+    // The password is "secret"
+    const REED_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$zEe8dxa59AAbwevrZh8QCA$1hO36k9X+RkS9dy7dWemIid86yVnIYhy+Qeuxi7RiGo";
+
+    fn user(nick: &str, role: Role) -> UserConfig {
+        UserConfig {
+            nick: String::from(nick),
+            password_hash: String::from(REED_HASH),
+            role,
+        }
+    }
+
+    fn room(name: &str) -> RoomConfig {
+        RoomConfig {
+            name: String::from(name),
+            topic: None,
+        }
+    }
+
+    // Validates `config`, which must fail, and returns the problems found
+    fn problems(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            [limits]
+            max_connections = 10
+
+            [[rooms]]
+            name = "baxter-building"
+            topic = "Fantastic Four HQ"
+
+            [[auth.users]]
+            nick = "Reed"
+            password_hash = "$argon2id$v=19$m=19456,t=2,p=1$zEe8dxa59AAbwevrZh8QCA$1hO36k9X+RkS9dy7dWemIid86yVnIYhy+Qeuxi7RiGo"
+            role = "operator"
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.limits.max_connections, 10);
+        assert_eq!(config.limits.max_connections_per_ip, 16);
+        assert_eq!(config.default_room(), "baxter-building");
+        assert_eq!(config.role("REED"), Role::Operator);
+        assert_eq!(config.role("Sue"), Role::User);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        assert!(toml::from_str::<Config>("[limits]\nmax_conections = 1").is_err());
+    }
+
+    #[test]
+    fn test_passwords() {
+        let mut config = Config::default();
+        config.auth.users.push(user("Reed", Role::User));
+        assert!(config.user("reed").unwrap().check_password("secret"));
+        assert!(!config.user("reed").unwrap().check_password("Secret"));
+
+        let user = UserConfig {
+            nick: String::from("Sue"),
+            password_hash: hash_password("invisible").unwrap(),
+            role: Role::User,
+        };
+        assert!(user.check_password("invisible"));
+        assert_ne!(user.password_hash, hash_password("invisible").unwrap());
+    }
+
+    #[test]
+    fn test_motd_set_twice() {
+        let mut config = Config::default();
+        config.server.motd = Some(String::from("Welcome"));
+        config.server.motd_file = Some("motd.txt".into());
+        assert!(problems(&config).contains("server.motd and server.motd_file can't both be set"));
+    }
+
+    #[test]
+    fn test_motd_file_missing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.server.motd_file = Some(dir.path().join("missing.txt"));
+        assert!(problems(&config).contains("server.motd_file: "));
+    }
+
+    #[test]
+    fn test_no_listen_addrs() {
+        let mut config = Config::default();
+        config.listen.addrs.clear();
+        assert!(problems(&config).contains("listen.addrs must list at least one address"));
+    }
+
+    #[test]
+    fn test_zero_limits() {
+        let mut config = Config::default();
+        config.limits.max_connections = 0;
+        config.limits.max_frame_length = 0;
+        let error = problems(&config);
+        assert!(error.contains("limits.max_connections must be at least 1"));
+        assert!(error.contains("limits.max_frame_length must be at least 1"));
+        assert!(!error.contains("limits.max_connections_per_ip"));
+    }
+
+    #[test]
+    fn test_room_names() {
+        let config = Config {
+            rooms: vec![room("lobby"), room(" "), room("lobby")],
+            ..Default::default()
+        };
+        let error = problems(&config);
+        assert!(error.contains("rooms: room names must not be empty"));
+        assert!(error.contains("rooms: \"lobby\" is defined more than once"));
+    }
+
+    #[test]
+    fn test_user_nicks() {
+        let mut config = Config::default();
+        config.auth.users = vec![
+            user("Reed", Role::User),
+            user("", Role::User),
+            user("REED", Role::User),
+        ];
+        let error = problems(&config);
+        assert!(error.contains("auth.users: nicknames must not be empty"));
+        assert!(error.contains("auth.users: \"REED\" is defined more than once"));
+    }
+
+    #[test]
+    fn test_password_hash_not_argon2() {
+        let mut config = Config::default();
+        config.auth.users.push(UserConfig {
+            nick: String::from("Reed"),
+            password_hash: String::from(
+                "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+            ),
+            role: Role::User,
+        });
+        assert!(problems(&config)
+            .contains("auth.users: password_hash for \"Reed\" must be an argon2 PHC string"));
+    }
+
+    #[test]
+    fn test_roles_need_registration() {
+        let mut config = Config::default();
+        config.auth.users.push(user("Reed", Role::User));
+        config.auth.operators.push(String::from("reed"));
+        config.auth.moderators.push(String::from("Sue"));
+        let error = problems(&config);
+        assert!(error.contains("auth.moderators: \"Sue\" must be registered in auth.users"));
+        assert!(!error.contains("auth.operators"));
+        assert_eq!(config.role("Sue"), Role::User);
+    }
+
+    #[test]
+    fn test_registration_without_users() {
+        let mut config = Config::default();
+        config.auth.require_registration = true;
+        assert!(
+            problems(&config).contains("auth.require_registration is set but auth.users is empty")
+        );
+
+        config.auth.users.push(user("Reed", Role::User));
+        config.validate().unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+/// Liveness and readiness checks for process supervisors
+///
+/// `/healthz` answers as long as the server is running at all, while
+/// `/readyz` only succeeds when it can accept clients and store data.
+use crate::{http::Response, state::State};
+use std::{fmt::Write as _, fs};
+
+/// Serves `/healthz` and `/readyz`
+pub fn route(state: &State, path: &str) -> Option<Response> {
+    match path {
+        "/healthz" => Some(Response::text(format!(
+            "ok\nclients: {}\n",
+            state.clients().len()
+        ))),
+        "/readyz" => Some(readiness(state)),
+        _ => None,
+    }
+}
+
+fn readiness(state: &State) -> Response {
+    let listeners = state.listeners();
+    // Not ready until the listeners have started, nor while any one is down
+    let accepting = !listeners.is_empty() && listeners.iter().all(|&(_, ok)| ok);
+    let writable = check_persistence(state);
+    let mut body = String::new();
+    if listeners.is_empty() {
+        let _ = writeln!(body, "listener: not started");
+    }
+    for (addr, ok) in listeners {
+        let status = if ok { "ok" } else { "not accepting" };
+        let _ = writeln!(body, "listener {}: {}", addr, status);
+    }
+    let _ = match &writable {
+        Ok(()) => writeln!(body, "persistence: ok"),
+        Err(e) => writeln!(body, "persistence: {}", e),
+    };
+    let _ = writeln!(body, "clients: {}", state.clients().len());
+    if accepting && writable.is_ok() {
+        Response::text(format!("ok\n{}", body))
+    } else {
+        Response::unavailable(format!("not ready\n{}", body))
+    }
+}
+
+// Makes sure files can still be created in the data directory, which is
+// where bans and the moderation log are written
+fn check_persistence(state: &State) -> Result<(), String> {
+    let data_dir = state.config().persistence.data_dir.clone();
+    let probe = data_dir.join(".simplechat-readyz");
+    fs::write(&probe, b"ok")
+        .and_then(|()| fs::remove_file(&probe))
+        .map_err(|e| format!("{} is not writable: {}", data_dir.display(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::route;
+    use crate::{
+        bans::BanList,
+        config::{Config, ConfigSource},
+        mailbox::Mailbox,
+        messages::MessageStore,
+        moderation::ModerationLog,
+        reads::ReadMarkers,
+        state::State,
+    };
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    // Stores everything in `dir` but probes `data_dir` for readiness
+    fn state(dir: &Path, data_dir: PathBuf) -> State {
+        let mut config = Config::default();
+        config.persistence.data_dir = data_dir;
+        State::new(
+            ConfigSource::new(None, Default::default()),
+            config,
+            BanList::load(dir.join("bans.txt")).unwrap(),
+            MessageStore::load(dir.join("messages.jsonl"), 10).unwrap(),
+            ReadMarkers::load(dir.join("read_markers.json")).unwrap(),
+            Mailbox::load(dir.join("mailbox.jsonl"), 10).unwrap(),
+            ModerationLog::new(dir.join("moderation.log")),
+        )
+    }
+
+    #[test]
+    fn test_healthz() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), dir.path().join("missing"));
+        let response = route(&state, "/healthz").unwrap();
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(response.body, "ok\nclients: 0\n");
+    }
+
+    #[test]
+    fn test_unknown_path() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), dir.path().to_path_buf());
+        assert!(route(&state, "/").is_none());
+        assert!(route(&state, "/readyz/extra").is_none());
+    }
+
+    #[test]
+    fn test_ready() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), dir.path().to_path_buf());
+        state.set_accepting("127.0.0.1:7000".parse().unwrap(), true);
+        state.set_accepting("[::1]:7000".parse().unwrap(), true);
+        let response = route(&state, "/readyz").unwrap();
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(
+            response.body,
+            "ok\nlistener 127.0.0.1:7000: ok\nlistener [::1]:7000: ok\n\
+             persistence: ok\nclients: 0\n"
+        );
+        assert!(!dir.path().join(".simplechat-readyz").exists());
+    }
+
+    #[test]
+    fn test_not_ready_before_listening() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), dir.path().to_path_buf());
+        let response = route(&state, "/readyz").unwrap();
+        assert_eq!(response.status, "503 Service Unavailable");
+        assert!(response.body.contains("listener: not started\n"));
+    }
+
+    #[test]
+    fn test_not_ready_while_one_listener_is_down() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), dir.path().to_path_buf());
+        state.set_accepting("127.0.0.1:7000".parse().unwrap(), true);
+        state.set_accepting("[::1]:7000".parse().unwrap(), false);
+        let response = route(&state, "/readyz").unwrap();
+        assert_eq!(response.status, "503 Service Unavailable");
+        assert_eq!(
+            response.body,
+            "not ready\nlistener 127.0.0.1:7000: ok\nlistener [::1]:7000: not accepting\n\
+             persistence: ok\nclients: 0\n"
+        );
+
+        state.set_accepting("[::1]:7000".parse().unwrap(), true);
+        assert_eq!(route(&state, "/readyz").unwrap().status, "200 OK");
+    }
+
+    #[test]
+    fn test_not_ready_without_persistence() {
+        let dir = TempDir::new().unwrap();
+        let missing = dir.path().join("missing");
+        let state = state(dir.path(), missing.clone());
+        state.set_accepting("127.0.0.1:7000".parse().unwrap(), true);
+        let response = route(&state, "/readyz").unwrap();
+        assert_eq!(response.status, "503 Service Unavailable");
+        let expected = format!("persistence: {} is not writable: ", missing.display());
+        assert!(response.body.contains(&expected), "{}", response.body);
+    }
+}
@@ -0,0 +1,126 @@
+/// Minimal HTTP/1.1 server for local monitoring endpoints
+///
+/// Only answers `GET` requests and closes the connection after each response,
+/// which is all that scrapers and supervisors need.
+use crate::{state::State, ACCEPT_RETRY_DELAY};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{debug, error};
+
+/// Longest request head that will be read
+const MAX_REQUEST_LENGTH: usize = 8192;
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Produces the response for a request path, or `None` if there is nothing
+/// there
+pub type Route = fn(&State, &str) -> Option<Response>;
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: &'static str, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::new("200 OK", "text/plain; charset=utf-8", body)
+    }
+
+    pub fn unavailable(body: impl Into<String>) -> Self {
+        Self::new("503 Service Unavailable", "text/plain; charset=utf-8", body)
+    }
+
+    fn not_found() -> Self {
+        Self::new("404 Not Found", "text/plain; charset=utf-8", "not found\n")
+    }
+
+    fn bad_request() -> Self {
+        Self::new(
+            "400 Bad Request",
+            "text/plain; charset=utf-8",
+            "bad request\n",
+        )
+    }
+
+    fn method_not_allowed() -> Self {
+        Self::new(
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            "method not allowed\n",
+        )
+    }
+}
+
+/// Answers requests on `listener` using `route` for as long as the server runs
+pub async fn serve(listener: TcpListener, state: Arc<State>, route: Route) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_request(stream, state.clone(), route));
+            }
+            Err(e) => {
+                error!("failed to accept http connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn handle_request(mut stream: TcpStream, state: Arc<State>, route: Route) {
+    let response = match timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(Some(line)) => {
+            debug!(request = %line, "http request");
+            match line.split(' ').collect::<Vec<_>>()[..] {
+                ["GET", target, _] => {
+                    let path = target.split('?').next().unwrap_or_default();
+                    route(&state, path).unwrap_or_else(Response::not_found)
+                }
+                [_, _, _] => Response::method_not_allowed(),
+                _ => Response::bad_request(),
+            }
+        }
+        Ok(None) => Response::bad_request(),
+        Err(_) => return,
+    };
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(response.body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+// Reads the request head and returns its first line, ignoring the headers
+async fn read_request_line(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        if buffer.len() > MAX_REQUEST_LENGTH {
+            return None;
+        }
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let head = String::from_utf8(buffer).ok()?;
+    head.lines().next().map(str::to_string)
+}
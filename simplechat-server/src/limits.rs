@@ -0,0 +1,169 @@
+/// Global and per-address connection limits
+use crate::bans::canonical;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Reason a connection was turned away
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LimitExceeded {
+    Total,
+    PerAddr,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Total => write!(f, "server is full"),
+            LimitExceeded::PerAddr => write!(f, "too many connections from your address"),
+        }
+    }
+}
+
+/// Tracks open connections and refuses new ones beyond the configured limits
+#[derive(Debug)]
+pub struct ConnectionLimits {
+    max_total: AtomicUsize,
+    max_per_addr: AtomicUsize,
+    counts: Mutex<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    total: usize,
+    per_addr: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimits {
+    pub fn new(max_total: usize, max_per_addr: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_total: AtomicUsize::new(max_total),
+            max_per_addr: AtomicUsize::new(max_per_addr),
+            counts: Mutex::default(),
+        })
+    }
+
+    /// Changes the limits. Connections already over a lowered limit are kept,
+    /// but no new ones are accepted until enough of them close.
+    pub fn set_maximums(&self, max_total: usize, max_per_addr: usize) {
+        self.max_total.store(max_total, Ordering::Relaxed);
+        self.max_per_addr.store(max_per_addr, Ordering::Relaxed);
+    }
+
+    /// Reserves a connection slot for `addr`, released when the returned
+    /// guard is dropped
+    pub fn acquire(self: &Arc<Self>, addr: IpAddr) -> Result<ConnectionGuard, LimitExceeded> {
+        let addr = canonical(addr);
+        let mut counts = self.counts.lock().unwrap();
+        if counts.total >= self.max_total.load(Ordering::Relaxed) {
+            return Err(LimitExceeded::Total);
+        }
+        let per_addr = counts.per_addr.entry(addr).or_default();
+        if *per_addr >= self.max_per_addr.load(Ordering::Relaxed) {
+            return Err(LimitExceeded::PerAddr);
+        }
+        *per_addr += 1;
+        counts.total += 1;
+        Ok(ConnectionGuard {
+            limits: Arc::clone(self),
+            addr,
+        })
+    }
+
+    fn release(&self, addr: IpAddr) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(per_addr) = counts.per_addr.get_mut(&addr) {
+            *per_addr -= 1;
+            if *per_addr == 0 {
+                counts.per_addr.remove(&addr);
+            }
+        }
+    }
+}
+
+/// Holds a connection slot for as long as the connection is open
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    limits: Arc<ConnectionLimits>,
+    addr: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.limits.release(self.addr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConnectionLimits, LimitExceeded};
+    use std::net::IpAddr;
+
+    fn addr(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_total_limit() {
+        let limits = ConnectionLimits::new(2, 10);
+        let _first = limits.acquire(addr("10.0.0.1")).unwrap();
+        let _second = limits.acquire(addr("10.0.0.2")).unwrap();
+        let third = limits.acquire(addr("10.0.0.3"));
+        assert_eq!(third.unwrap_err(), LimitExceeded::Total);
+    }
+
+    #[test]
+    fn test_per_addr_limit() {
+        let limits = ConnectionLimits::new(10, 2);
+        let _first = limits.acquire(addr("10.0.0.1")).unwrap();
+        let _second = limits.acquire(addr("10.0.0.1")).unwrap();
+        let third = limits.acquire(addr("10.0.0.1"));
+        assert_eq!(third.unwrap_err(), LimitExceeded::PerAddr);
+        assert!(
+            limits.acquire(addr("10.0.0.2")).is_ok(),
+            "others can connect"
+        );
+    }
+
+    #[test]
+    fn test_dropping_guard_releases_slot() {
+        let limits = ConnectionLimits::new(1, 1);
+        let first = limits.acquire(addr("10.0.0.1")).unwrap();
+        assert!(limits.acquire(addr("10.0.0.1")).is_err());
+        drop(first);
+        let again = limits.acquire(addr("10.0.0.1"));
+        assert!(again.is_ok(), "both limits released");
+    }
+
+    #[test]
+    fn test_mapped_addresses_count_as_ipv4() {
+        let limits = ConnectionLimits::new(10, 1);
+        let _v4 = limits.acquire(addr("10.0.0.1")).unwrap();
+        let mapped = limits.acquire(addr("::ffff:10.0.0.1"));
+        assert_eq!(mapped.unwrap_err(), LimitExceeded::PerAddr);
+    }
+
+    #[test]
+    fn test_lowered_limits_keep_existing_connections() {
+        let limits = ConnectionLimits::new(2, 2);
+        let first = limits.acquire(addr("10.0.0.1")).unwrap();
+        let _second = limits.acquire(addr("10.0.0.1")).unwrap();
+        limits.set_maximums(1, 1);
+        assert_eq!(
+            limits.acquire(addr("10.0.0.2")).unwrap_err(),
+            LimitExceeded::Total
+        );
+        drop(first);
+        assert!(
+            limits.acquire(addr("10.0.0.2")).is_err(),
+            "still one too many"
+        );
+    }
+}
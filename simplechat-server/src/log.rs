@@ -0,0 +1,106 @@
+/// Structured logging of server events through `tracing`
+use crate::config::LoggingConfig;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt as format,
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+    Registry,
+};
+
+/// Severity of a log message, in decreasing order of importance
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+            Level::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+impl From<Level> for LevelFilter {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => LevelFilter::ERROR,
+            Level::Warn => LevelFilter::WARN,
+            Level::Info => LevelFilter::INFO,
+            Level::Debug => LevelFilter::DEBUG,
+            Level::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// How each log line is written
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Human readable text
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+static FILTER: OnceLock<reload::Handle<Targets, Registry>> = OnceLock::new();
+
+// Builds the filter for the configured default level and per-target levels
+fn filter(config: &LoggingConfig) -> Targets {
+    Targets::new().with_default(config.level).with_targets(
+        config
+            .targets
+            .iter()
+            .map(|(target, level)| (target.clone(), LevelFilter::from(*level))),
+    )
+}
+
+/// Starts writing log events to stdout. The format is fixed from here on,
+/// but levels can be changed later with `reconfigure`.
+pub fn init(config: &LoggingConfig) -> Result<()> {
+    let (filter, handle) = reload::Layer::new(filter(config));
+    let (text, json) = match config.format {
+        Format::Text => (
+            Some(format::layer().with_ansi(io::stdout().is_terminal())),
+            None,
+        ),
+        Format::Json => (None, Some(format::layer().json())),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .try_init()?;
+    FILTER
+        .set(handle)
+        .map_err(|_| anyhow!("logging was already initialized"))
+}
+
+/// Applies the levels from `config` to everything logged from now on
+pub fn reconfigure(config: &LoggingConfig) -> Result<()> {
+    if let Some(handle) = FILTER.get() {
+        handle.reload(filter(config))?;
+    }
+    Ok(())
+}
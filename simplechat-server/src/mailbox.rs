@@ -0,0 +1,336 @@
+/// Private messages, and mentions kept for registered users while offline
+use crate::{
+    messages::{self, StoredMessage},
+    state::{Control, RoomName, State},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use simplechat_protocol::{mentions, same_nick, MessageId, ReceivedMessage, ServerFrame};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Something waiting for a registered user to log in
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Queued {
+    Direct {
+        to: String,
+        from: String,
+        text: String,
+        ts: String,
+    },
+    Mention {
+        to: String,
+        room: RoomName,
+        id: MessageId,
+        author: String,
+        text: String,
+        ts: String,
+    },
+}
+
+impl Queued {
+    fn to(&self) -> &str {
+        match self {
+            Queued::Direct { to, .. } | Queued::Mention { to, .. } => to,
+        }
+    }
+
+    pub fn to_frame(&self) -> ServerFrame {
+        match self.clone() {
+            Queued::Direct { to, from, text, ts } => ServerFrame::direct(from, to, text, ts),
+            Queued::Mention {
+                room,
+                id,
+                author,
+                text,
+                ts,
+                ..
+            } => ServerFrame::mentioned(room, ReceivedMessage::new(id, author, text, ts)),
+        }
+    }
+}
+
+/// Reasons a private message can't be sent
+#[derive(Debug, Error)]
+pub enum DirectError {
+    #[error("join a room first")]
+    NotJoined,
+    #[error("{0} is not online")]
+    NotConnected(String),
+    #[error("messages can't be empty")]
+    Empty,
+}
+
+/// What became of a private message
+#[derive(Debug)]
+pub enum Delivery {
+    /// Sent to every connection of the recipient, as this frame
+    Delivered(ServerFrame),
+    /// Kept until the recipient, who is registered, next logs in
+    Queued,
+}
+
+/// Items waiting for offline users, in the order they arrived, kept in a file
+/// with one JSON object per line that `flush` rewrites after any change
+#[derive(Debug)]
+pub struct Mailbox {
+    path: PathBuf,
+    items: Vec<Queued>,
+    /// How many items to keep for each user
+    limit: usize,
+    /// Whether items have changed since the file was last written
+    dirty: bool,
+}
+
+impl Mailbox {
+    /// Loads waiting items from `path`. A missing file is an empty mailbox.
+    pub fn load(path: impl Into<PathBuf>, limit: usize) -> Result<Self> {
+        let path = path.into();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        let items = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("{} line {}: invalid item", path.display(), number + 1)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        info!(path = %path.display(), items = items.len(), "loaded offline mailbox");
+        Ok(Self {
+            path,
+            items,
+            limit,
+            dirty: false,
+        })
+    }
+
+    /// Keeps `item` for its recipient, dropping their oldest item if they
+    /// already have as many as the limit. It is saved by the next `flush`.
+    pub fn push(&mut self, item: Queued) {
+        let to = item.to().to_string();
+        self.items.push(item);
+        let waiting: Vec<_> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, waiting)| same_nick(waiting.to(), &to))
+            .map(|(index, _)| index)
+            .collect();
+        let excess = waiting.len().saturating_sub(self.limit);
+        for index in waiting[..excess].iter().rev() {
+            self.items.remove(*index);
+        }
+        self.dirty = true;
+    }
+
+    /// Removes and returns everything waiting for `nick`, oldest first
+    pub fn take(&mut self, nick: &str) -> Vec<Queued> {
+        let (taken, kept): (Vec<_>, _) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| same_nick(item.to(), nick));
+        self.items = kept;
+        self.dirty |= !taken.is_empty();
+        taken
+    }
+
+    /// Writes the items out if they have changed since last time. The file is
+    /// written without holding the lock on `mailbox`.
+    pub fn flush(mailbox: &Mutex<Self>) {
+        let (path, contents) = {
+            let mut mailbox = mailbox.lock().unwrap();
+            if !mailbox.dirty {
+                return;
+            }
+            let contents: Result<String, _> = mailbox
+                .items
+                .iter()
+                .map(|item| serde_json::to_string(item).map(|line| line + "\n"))
+                .collect();
+            match contents {
+                Ok(contents) => {
+                    mailbox.dirty = false;
+                    (mailbox.path.clone(), contents)
+                }
+                Err(e) => {
+                    error!(path = %mailbox.path.display(), "failed to save offline mailbox: {}", e);
+                    return;
+                }
+            }
+        };
+        if let Err(e) = write(&path, &contents) {
+            error!(path = %path.display(), "failed to save offline mailbox: {:#}", e);
+            mailbox.lock().unwrap().dirty = true;
+        }
+    }
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    // Written beside the real file and renamed over it, so a crash can't
+    // leave it half written
+    let temp = path.with_extension("jsonl.tmp");
+    fs::write(&temp, contents)
+        .and_then(|_| fs::rename(&temp, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Sends a private message from `from` to `to`, or keeps it until they log in
+/// if they are offline and registered
+pub fn direct(
+    state: &State,
+    from: &str,
+    room: Option<&str>,
+    to: &str,
+    text: &str,
+) -> Result<Delivery, DirectError> {
+    room.ok_or(DirectError::NotJoined)?;
+    if text.trim().is_empty() {
+        return Err(DirectError::Empty);
+    }
+    let recipients = state.find_by_nick(to);
+    if let Some(recipient) = recipients.first() {
+        let frame = ServerFrame::direct(from, &recipient.nick, text, messages::now());
+        for recipient in &recipients {
+            let _ = recipient.control.send(Control::Deliver(frame.clone()));
+        }
+        return Ok(Delivery::Delivered(frame));
+    }
+    let config = state.config();
+    let user = config
+        .user(to)
+        .ok_or_else(|| DirectError::NotConnected(to.to_string()))?;
+    let item = Queued::Direct {
+        to: user.nick.clone(),
+        from: from.to_string(),
+        text: text.to_string(),
+        ts: messages::now(),
+    };
+    state.mailbox.lock().unwrap().push(item);
+    Ok(Delivery::Queued)
+}
+
+/// Keeps `message` for each offline registered user it mentions
+pub fn queue_mentions(state: &State, message: &StoredMessage) {
+    let config = state.config();
+    let items: Vec<_> = config
+        .auth
+        .users
+        .iter()
+        .filter(|user| {
+            !same_nick(&user.nick, &message.author)
+                && mentions(&message.text, &user.nick)
+                && state.find_by_nick(&user.nick).is_empty()
+        })
+        .map(|user| Queued::Mention {
+            to: user.nick.clone(),
+            room: message.room.clone(),
+            id: message.id,
+            author: message.author.clone(),
+            text: message.text.clone(),
+            ts: message.ts.clone(),
+        })
+        .collect();
+    if items.is_empty() {
+        return;
+    }
+    let mut mailbox = state.mailbox.lock().unwrap();
+    for item in items {
+        mailbox.push(item);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mailbox, Queued};
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn direct(to: &str, text: &str) -> Queued {
+        Queued::Direct {
+            to: to.to_string(),
+            from: String::from("Reed"),
+            text: text.to_string(),
+            ts: String::from("2000-01-01T00:00:00Z"),
+        }
+    }
+
+    fn mailbox(dir: &TempDir) -> Mailbox {
+        Mailbox::load(dir.path().join("mailbox.jsonl"), 2).unwrap()
+    }
+
+    // Writes out `mailbox` and loads the file again
+    fn reload(queued: Mailbox, dir: &TempDir) -> Mailbox {
+        Mailbox::flush(&Mutex::new(queued));
+        mailbox(dir)
+    }
+
+    #[test]
+    fn test_take_survives_reload() {
+        let dir = TempDir::new().unwrap();
+        let mut sent = mailbox(&dir);
+        sent.push(direct("Sue", "one"));
+        sent.push(direct("Ben", "two"));
+
+        let mut loaded = reload(sent, &dir);
+        assert_eq!(loaded.take("Sue"), [direct("Sue", "one")]);
+        assert!(loaded.take("Sue").is_empty());
+
+        let mut reloaded = reload(loaded, &dir);
+        assert!(reloaded.take("Sue").is_empty(), "taken for good");
+        assert_eq!(reloaded.take("Ben"), [direct("Ben", "two")]);
+    }
+
+    #[test]
+    fn test_nicknames_ignore_case() {
+        let dir = TempDir::new().unwrap();
+        let mut queued = mailbox(&dir);
+        queued.push(direct("Sue", "one"));
+        queued.push(direct("sue", "two"));
+        let taken = queued.take("SUE");
+        assert_eq!(taken, [direct("Sue", "one"), direct("sue", "two")]);
+    }
+
+    #[test]
+    fn test_drops_oldest_beyond_limit() {
+        let dir = TempDir::new().unwrap();
+        let mut sent = mailbox(&dir);
+        sent.push(direct("Sue", "one"));
+        sent.push(direct("Ben", "two"));
+        sent.push(direct("Sue", "three"));
+        sent.push(direct("Sue", "four"));
+
+        let mut loaded = reload(sent, &dir);
+        let taken = loaded.take("Sue");
+        assert_eq!(taken, [direct("Sue", "three"), direct("Sue", "four")]);
+        assert_eq!(loaded.take("Ben"), [direct("Ben", "two")], "kept per user");
+    }
+
+    #[test]
+    fn test_written_only_when_flushed() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("mailbox.jsonl");
+        let shared = Mutex::new(mailbox(&dir));
+        shared.lock().unwrap().push(direct("Sue", "one"));
+        assert!(!path.exists());
+        Mailbox::flush(&shared);
+        assert_eq!(mailbox(&dir).take("Sue"), [direct("Sue", "one")]);
+
+        std::fs::remove_file(&path).unwrap();
+        Mailbox::flush(&shared);
+        assert!(!path.exists(), "unchanged since");
+        assert!(shared.lock().unwrap().take("Ben").is_empty());
+        Mailbox::flush(&shared);
+        assert!(!path.exists(), "taking nothing changes nothing");
+    }
+}
@@ -1,95 +1,676 @@
 /// Simple chat server
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use futures::{SinkExt, TryStreamExt};
+use futures::{future, SinkExt, TryStreamExt};
 use simplechat_protocol::{
-    ClientFrame, ClientFrameCodec, ReceivedMessage, ServerFrame, ServerFrameCodec,
+    same_nick, ClientFrame, ClientFrameCodec, ServerFrame, ServerFrameCodec,
 };
 use std::{
+    fs,
     net::SocketAddr,
-    sync::atomic::{AtomicUsize, Ordering},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{
+    io::WriteHalf,
     net::{TcpListener, TcpStream},
-    sync::broadcast,
+    sync::{broadcast::error::RecvError, mpsc},
 };
 use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
-// Types used by broadcast channel to distribute messages
-type ClientId = usize;
-type RelayedMessage = (ClientId, ReceivedMessage);
+#[cfg(unix)]
+mod admin;
+mod bans;
+mod config;
+mod health;
+mod http;
+mod limits;
+mod log;
+mod mailbox;
+mod messages;
+mod metrics;
+mod moderation;
+mod motd;
+mod reads;
+mod state;
+
+use bans::BanList;
+use config::{ConfigSource, Overrides};
+use limits::ConnectionGuard;
+use mailbox::{Delivery, Mailbox};
+use messages::{MessageError, MessageStore, StoredMessage};
+use metrics::{CountingStream, FrameError};
+use moderation::{Actor, ModerationError, ModerationLog};
+use reads::ReadMarkers;
+use state::{ClientHandle, ClientId, Control, RoomName, State};
 
 const DEFAULT_NAME: &str = "Anonymous";
 
+/// How long to wait before accepting again after a failure
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How often to check the ban list file for changes
+const BAN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often to write out changes kept back to batch them up
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+type ClientWriter = FramedWrite<WriteHalf<CountingStream<TcpStream>>, ServerFrameCodec>;
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Bind to this addr
-    #[arg(short, long, default_value = "localhost:3000")]
-    addr: String,
+    /// Read settings from this TOML file
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Read a password from standard input, print its hash for `auth.users`
+    /// and exit
+    #[arg(long)]
+    hash_password: bool,
+
+    #[command(flatten)]
+    overrides: Overrides,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    let listener = TcpListener::bind(args.addr).await?;
-    let (relay_tx, _relay_rx) = broadcast::channel::<RelayedMessage>(256);
-    let client_id = AtomicUsize::from(0);
+    if args.hash_password {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        let password = password.trim_end_matches(['\r', '\n']);
+        println!("{}", config::hash_password(password)?);
+        return Ok(());
+    }
+    let source = ConfigSource::new(args.config, args.overrides);
+    let config = source.load()?;
+    log::init(&config.logging)?;
 
+    let persistence = &config.persistence;
+    fs::create_dir_all(&persistence.data_dir).with_context(|| {
+        format!(
+            "failed to create data directory {}",
+            persistence.data_dir.display()
+        )
+    })?;
+    let bans = BanList::load(persistence.ban_file())?;
+    let messages = MessageStore::load(persistence.message_file(), persistence.history_limit)?;
+    let reads = ReadMarkers::load(persistence.read_marker_file())?;
+    let mailbox = Mailbox::load(persistence.mailbox_file(), persistence.offline_limit)?;
+    let modlog = ModerationLog::new(persistence.moderation_log());
+    let mut listeners = Vec::new();
+    for addr in &config.listen.addrs {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to listen on {}", addr))?;
+        info!(addr = %listener.local_addr()?, "listening");
+        listeners.push(listener);
+    }
+    #[cfg(unix)]
+    let admin_listener = admin::bind(&config.listen.admin_socket)?;
+    let metrics_listener = bind_http(config.listen.metrics_addr.as_deref(), "metrics").await?;
+    let health_listener = bind_http(config.listen.health_addr.as_deref(), "health checks").await?;
+
+    let state = Arc::new(State::new(
+        source, config, bans, messages, reads, mailbox, modlog,
+    ));
+    #[cfg(unix)]
+    tokio::spawn(admin::serve(admin_listener, state.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(state.clone()));
+    tokio::spawn(poll_bans(state.clone()));
+    tokio::spawn(flush_stores(state.clone()));
+    if let Some(listener) = metrics_listener {
+        tokio::spawn(http::serve(listener, state.clone(), metrics::route));
+    }
+    if let Some(listener) = health_listener {
+        tokio::spawn(http::serve(listener, state.clone(), health::route));
+    }
+    let client_id = Arc::new(AtomicUsize::from(0));
+    let mut accept_loops = Vec::new();
+    for listener in listeners {
+        let local_addr = listener.local_addr()?;
+        state.set_accepting(local_addr, true);
+        let accepting = accept_clients(listener, local_addr, state.clone(), client_id.clone());
+        accept_loops.push(tokio::spawn(accepting));
+    }
+    let (result, _, _) = future::select_all(accept_loops).await;
+    Ok(result?)
+}
+
+// Binds the listener for an optional HTTP endpoint
+async fn bind_http(addr: Option<&str>, what: &str) -> Result<Option<TcpListener>> {
+    let Some(addr) = addr else {
+        return Ok(None);
+    };
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to serve {} on {}", what, addr))?;
+    info!(addr = %listener.local_addr()?, "serving {}", what);
+    Ok(Some(listener))
+}
+
+// Re-reads the configuration whenever the process receives SIGHUP
+#[cfg(unix)]
+async fn reload_on_hangup(state: Arc<State>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        if let Err(e) = state.reload_config() {
+            error!("failed to reload configuration: {:#}", e);
+        }
+    }
+}
+
+// Picks up hand edits to the ban list, off the accept and join paths
+async fn poll_bans(state: Arc<State>) {
+    let mut interval = tokio::time::interval(BAN_POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
     loop {
-        let (stream, addr) = listener.accept().await?;
-        tokio::spawn(handle_client(
-            client_id.fetch_add(1, Ordering::Relaxed),
-            stream,
-            addr,
-            relay_tx.clone(),
-            relay_tx.subscribe(),
-        ));
+        interval.tick().await;
+        let state = state.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || BanList::poll(&state.bans)).await {
+            error!("failed to check the ban list: {}", e);
+        }
+    }
+}
+
+// Writes out changes that are batched up rather than saved straight away
+async fn flush_stores(state: Arc<State>) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let state = state.clone();
+        let flushed = tokio::task::spawn_blocking(move || {
+            MessageStore::flush(&state.messages);
+            ReadMarkers::flush(&state.reads);
+            Mailbox::flush(&state.mailbox);
+        });
+        if let Err(e) = flushed.await {
+            error!("failed to write out changes: {}", e);
+        }
+    }
+}
+
+async fn accept_clients(
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    state: Arc<State>,
+    client_id: Arc<AtomicUsize>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => {
+                state.set_accepting(local_addr, true);
+                accepted
+            }
+            Err(e) => {
+                // Usually out of file descriptors, which may pass once some
+                // connections close
+                error!("failed to accept connection: {}", e);
+                state.set_accepting(local_addr, false);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        // Banned ranges and clients' addresses are compared with mapped IPv4
+        // addresses unwrapped
+        let addr = SocketAddr::new(bans::canonical(addr.ip()), addr.port());
+        if state.bans.lock().unwrap().is_addr_banned(addr.ip()) {
+            info!(%addr, "rejected connection from banned address");
+            state.stats.rejected.fetch_add(1, Ordering::Relaxed);
+            let frame = ServerFrame::closed("you are banned from this server");
+            tokio::spawn(reject_client(stream, frame));
+            continue;
+        }
+        let guard = match state.limits.acquire(addr.ip()) {
+            Ok(guard) => guard,
+            Err(e) => {
+                info!(%addr, reason = %e, "rejected connection");
+                state.stats.rejected.fetch_add(1, Ordering::Relaxed);
+                // Worth trying again once others have left
+                tokio::spawn(reject_client(stream, ServerFrame::error(e.to_string())));
+                continue;
+            }
+        };
+        state.stats.accepted.fetch_add(1, Ordering::Relaxed);
+        let client_id = client_id.fetch_add(1, Ordering::Relaxed);
+        // Everything logged on behalf of this client carries these fields
+        let span = info_span!(
+            "client",
+            id = client_id,
+            %addr,
+            nick = field::Empty,
+            room = field::Empty
+        );
+        tokio::spawn(handle_client(client_id, stream, addr, guard, state.clone()).instrument(span));
     }
 }
 
+// Decides whether `nick` may join `requested`, returning the room to join or
+// the reason they can't
+async fn admit(
+    state: &State,
+    nick: &str,
+    requested: &str,
+    password: Option<String>,
+) -> Result<RoomName, String> {
+    if state.bans.lock().unwrap().is_nick_banned(nick) {
+        return Err(String::from("that nickname is banned"));
+    }
+    let config = state.config();
+    match (config.user(nick), password) {
+        (Some(user), Some(password)) => {
+            let user = user.clone();
+            let checked = tokio::task::spawn_blocking(move || user.check_password(&password)).await;
+            if !checked.unwrap_or(false) {
+                return Err(String::from("wrong password"));
+            }
+        }
+        (Some(_), None) => {
+            return Err(String::from(
+                "that nickname is registered, a password is required",
+            ))
+        }
+        (None, _) if config.auth.require_registration => {
+            return Err(String::from("only registered users may join"))
+        }
+        (None, _) => {}
+    }
+    if requested.is_empty() {
+        Ok(config.default_room().to_string())
+    } else if config.rooms.is_empty() || config.room(requested).is_some() {
+        Ok(requested.to_string())
+    } else {
+        Err(format!("there is no room called {:?}", requested))
+    }
+}
+
+// Tells a client why it is being turned away before closing the connection
+async fn reject_client(stream: TcpStream, frame: ServerFrame) {
+    let mut writer = FramedWrite::new(stream, ServerFrameCodec::default());
+    let _ = writer.send(frame).await;
+}
+
 async fn handle_client(
-    client_id: usize,
+    client_id: ClientId,
     stream: TcpStream,
     addr: SocketAddr,
-    relay_tx: broadcast::Sender<RelayedMessage>,
-    mut relay_rx: broadcast::Receiver<RelayedMessage>,
+    _guard: ConnectionGuard,
+    state: Arc<State>,
 ) {
-    println!("connection from {:?} assigned #{}", addr, client_id);
+    info!("connected");
+    let stream = CountingStream::new(stream, state.stats.clone());
     let (rx, tx) = tokio::io::split(stream);
-    let mut reader = FramedRead::new(rx, ClientFrameCodec::default());
+    let max_frame_length = state.config().limits.max_frame_length;
+    let mut reader = FramedRead::new(rx, ClientFrameCodec::new_with_max_length(max_frame_length));
     let mut writer = FramedWrite::new(tx, ServerFrameCodec::default());
     let mut name = String::from(DEFAULT_NAME);
+    let mut room: Option<RoomName> = None;
+    // Whether others were last told this client is typing
+    let mut typing = false;
+    let mut relay_rx = state.relay_tx.subscribe();
+    let (control_tx, mut control_rx) = mpsc::unbounded_channel();
+    state.register(
+        client_id,
+        ClientHandle {
+            addr,
+            nick: name.clone(),
+            room: None,
+            control: control_tx,
+            last_active: Instant::now(),
+        },
+    );
     loop {
         tokio::select! {
             // Receive messages from the client
             maybe_frame = reader.try_next() => {
-                if let Ok(Some(frame)) = maybe_frame {
-                    match frame {
-                        ClientFrame::Send(msg) => {
-                            name = msg.author.clone();
-                            if let Err(e) = relay_tx.send((client_id, msg.into())) {
-                                eprintln!("relay error: {:?}", e);
+                let frame = match maybe_frame {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(e) => {
+                        match FrameError::classify(&e) {
+                            Some(kind) => {
+                                state.stats.frame_rejected(kind);
+                                info!(error = %e, "dropping client after invalid frame");
                             }
+                            None => debug!(error = %e, "connection failed"),
                         }
-                        ClientFrame::Leave => {
-                            println!("{} left", name);
-                            break;
+                        break;
+                    }
+                };
+                state.touch(client_id);
+                match frame {
+                    ClientFrame::Join { nick, room: requested, password } => {
+                        let joined = match admit(&state, &nick, &requested, password).await {
+                            Ok(joined) => joined,
+                            Err(reason) => {
+                                info!(%nick, %requested, %reason, "refused join");
+                                let _ = writer.send(ServerFrame::closed(reason)).await;
+                                break;
+                            }
+                        };
+                        Span::current()
+                            .record("nick", nick.as_str())
+                            .record("room", joined.as_str());
+                        info!("joined");
+                        state.set_joined(client_id, &nick, &joined);
+                        if let Some(text) = motd::for_client(&state, &nick, &joined) {
+                            let _ = writer.send(ServerFrame::motd(text)).await;
+                        }
+                        let replay = state.config().persistence.history_replay;
+                        let recent = state.messages.lock().unwrap().recent(&joined, replay);
+                        for message in recent {
+                            send_stored(&mut writer, message).await;
                         }
+                        let read = state.reads.lock().unwrap().get(&nick, &joined);
+                        if let Some(id) = read {
+                            let _ = writer.send(ServerFrame::read_marker(id)).await;
+                        }
+                        for frame in seen_by(&state, &nick, &joined) {
+                            let _ = writer.send(frame).await;
+                        }
+                        // Only registered users can have anything waiting, and
+                        // admit has checked their password
+                        if state.config().user(&nick).is_some() {
+                            send_waiting(&state, &mut writer, &nick).await;
+                        }
+                        let topic = state
+                            .config()
+                            .room(&joined)
+                            .and_then(|room| room.topic.clone());
+                        if let Some(topic) = topic {
+                            let _ = writer.send(ServerFrame::notice(format!("topic: {}", topic))).await;
+                        }
+                        name = nick;
+                        room = Some(joined);
+                    }
+                    ClientFrame::Send(msg) => {
+                        // Refusals quote the nonce when there is one, so the
+                        // client knows which message didn't go through
+                        let nonce = msg.nonce.clone();
+                        let refusal = |reason: String| match &nonce {
+                            Some(nonce) => ServerFrame::rejected(nonce, reason),
+                            None => ServerFrame::error(reason),
+                        };
+                        let Some(room) = &room else {
+                            let _ = writer.send(refusal("join a room before sending".into())).await;
+                            continue;
+                        };
+                        if let Err(e) = state.check_muted(&name) {
+                            let _ = writer.send(refusal(e.to_string())).await;
+                            continue;
+                        }
+                        let (stored, new) = match messages::send(&state, &name, Some(room), msg) {
+                            Ok(sent) => sent,
+                            Err(e) => {
+                                let _ = writer.send(refusal(e.to_string())).await;
+                                continue;
+                            }
+                        };
+                        if new {
+                            mailbox::queue_mentions(&state, &stored);
+                            // Sent back to the sender too, which tells it the message's ID
+                            let msg = ServerFrame::receive(stored.to_received());
+                            match state.relay_tx.send((None, room.clone(), msg, Instant::now())) {
+                                Ok(_) => {
+                                    state.stats.relayed.fetch_add(1, Ordering::Relaxed);
+                                    debug!(id = stored.id, "relayed message");
+                                    // Clients stop showing the author as typing
+                                    // once their message arrives
+                                    typing = false;
+                                }
+                                Err(e) => error!("relay error: {}", e),
+                            }
+                        } else {
+                            debug!(id = stored.id, "message sent again");
+                        }
+                        if let Some(nonce) = nonce {
+                            let _ = writer.send(ServerFrame::ack(nonce, stored.id, stored.ts)).await;
+                        }
+                    }
+                    ClientFrame::Edit { id, text } => {
+                        if let Err(e) = state.check_muted(&name) {
+                            let _ = writer.send(ServerFrame::error(e.to_string())).await;
+                            continue;
+                        }
+                        match messages::edit(&state, &name, room.as_deref(), id, &text) {
+                            Ok(edited) => {
+                                let frame = ServerFrame::edited(id, edited.text, edited.edited.unwrap_or_default());
+                                let _ = state.relay_tx.send((None, edited.room, frame, Instant::now()));
+                                debug!(id, "edited message");
+                            }
+                            Err(e) => report_message_error(&mut writer, e).await,
+                        }
+                    }
+                    ClientFrame::Delete { id } => {
+                        match messages::delete(&state, &name, room.as_deref(), id) {
+                            Ok(deleted) => {
+                                let frame = ServerFrame::deleted(id);
+                                let _ = state.relay_tx.send((None, deleted.room, frame, Instant::now()));
+                                debug!(id, "deleted message");
+                            }
+                            Err(e) => report_message_error(&mut writer, e).await,
+                        }
+                    }
+                    ClientFrame::Thread { root } => {
+                        match messages::thread(&state, room.as_deref(), root) {
+                            Ok(thread) => {
+                                for message in thread {
+                                    send_stored(&mut writer, message).await;
+                                }
+                            }
+                            Err(e) => report_message_error(&mut writer, e).await,
+                        }
+                    }
+                    ClientFrame::React { id, emoji } => {
+                        if let Err(e) = state.check_muted(&name) {
+                            let _ = writer.send(ServerFrame::error(e.to_string())).await;
+                            continue;
+                        }
+                        match messages::react(&state, &name, room.as_deref(), id, &emoji) {
+                            Ok(message) => {
+                                let frame = ServerFrame::reactions(id, message.to_reactions());
+                                let _ = state.relay_tx.send((None, message.room, frame, Instant::now()));
+                            }
+                            Err(e) => report_message_error(&mut writer, e).await,
+                        }
+                    }
+                    ClientFrame::Kick { nick, reason } => {
+                        let result = Actor::joined(&name, room.as_deref())
+                            .and_then(|actor| moderation::kick(&state, &actor, &nick, &reason));
+                        report_moderation(&mut writer, result).await;
+                    }
+                    ClientFrame::Mute { nick, secs } => {
+                        let result = Actor::joined(&name, room.as_deref())
+                            .and_then(|actor| moderation::mute(&state, &actor, &nick, secs));
+                        report_moderation(&mut writer, result).await;
+                    }
+                    ClientFrame::Ban { target, reason } => {
+                        let result = Actor::joined(&name, room.as_deref())
+                            .and_then(|actor| moderation::ban(&state, &actor, &target, &reason));
+                        report_moderation(&mut writer, result).await;
+                    }
+                    ClientFrame::Unban { target } => {
+                        let result = Actor::joined(&name, room.as_deref())
+                            .and_then(|actor| moderation::unban(&state, &actor, &target));
+                        report_moderation(&mut writer, result).await;
+                    }
+                    ClientFrame::SetRole { nick, role } => {
+                        let result = Actor::joined(&name, room.as_deref())
+                            .and_then(|actor| moderation::set_role(&state, &actor, &nick, &role));
+                        report_moderation(&mut writer, result).await;
+                    }
+                    ClientFrame::Typing { active } => {
+                        // Only relayed, as it means nothing to anyone joining later
+                        let Some(room) = &room else { continue };
+                        if state.check_muted(&name).is_err() {
+                            continue;
+                        }
+                        typing = active;
+                        let frame = ServerFrame::typing(&name, active);
+                        let _ = state.relay_tx.send((Some(client_id), room.clone(), frame, Instant::now()));
+                    }
+                    ClientFrame::Seen { id } => {
+                        let Some(room) = &room else { continue };
+                        // Only registered users are remembered, as anyone could
+                        // take an unregistered nickname next
+                        if state.config().user(&name).is_none() {
+                            continue;
+                        }
+                        if state.messages.lock().unwrap().get(room, id).is_none() {
+                            continue;
+                        }
+                        let marked = state.reads.lock().unwrap().mark(&name, room, id);
+                        if marked && state.members(room).len() <= state.config().limits.seen_by_max_members {
+                            let frame = ServerFrame::seen(&name, id);
+                            let _ = state.relay_tx.send((Some(client_id), room.clone(), frame, Instant::now()));
+                        }
+                    }
+                    ClientFrame::Direct { to, text } => {
+                        if let Err(e) = state.check_muted(&name) {
+                            let _ = writer.send(ServerFrame::error(e.to_string())).await;
+                            continue;
+                        }
+                        match mailbox::direct(&state, &name, room.as_deref(), &to, &text) {
+                            // Sent back so the sender sees it among the others
+                            Ok(Delivery::Delivered(frame)) => {
+                                let _ = writer.send(frame).await;
+                            }
+                            Ok(Delivery::Queued) => {
+                                let text = format!("{} is offline and will get your message when they next log in", to);
+                                let _ = writer.send(ServerFrame::notice(text)).await;
+                            }
+                            Err(e) => {
+                                let _ = writer.send(ServerFrame::error(e.to_string())).await;
+                            }
+                        }
+                    }
+                    ClientFrame::Leave => {
+                        info!("left");
+                        break;
                     }
-                } else {
-                    break;
                 }
             }
 
             // Forward messages to the client
             maybe_msg = relay_rx.recv() => {
-                if let Ok((sender_id, msg)) = maybe_msg {
-                    if sender_id != client_id {
-                        writer.send(ServerFrame::receive(msg)).await.unwrap();
+                match maybe_msg {
+                    Ok((sender_id, msg_room, frame, relayed_at)) => {
+                        if sender_id != Some(client_id) && room.as_ref() == Some(&msg_room) {
+                            if let Err(e) = writer.send(frame).await {
+                                debug!(error = %e, "connection failed");
+                                break;
+                            }
+                            state.stats.relay_latency.observe(relayed_at.elapsed());
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        state.stats.lag_events.fetch_add(1, Ordering::Relaxed);
+                        state.stats.lagged_messages.fetch_add(missed, Ordering::Relaxed);
+                        warn!(missed, "fell behind relayed messages");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+
+            // Act on instructions from elsewhere in the server
+            Some(control) = control_rx.recv() => {
+                match control {
+                    Control::Disconnect(reason) => {
+                        info!(%reason, "disconnected by the server");
+                        let _ = writer.send(ServerFrame::closed(reason)).await;
+                        break;
+                    }
+                    Control::Deliver(frame) => {
+                        if let Err(e) = writer.send(frame).await {
+                            debug!(error = %e, "connection failed");
+                            break;
+                        }
                     }
                 }
             }
         }
     }
+    if let (true, Some(room)) = (typing, room) {
+        let frame = ServerFrame::typing(&name, false);
+        let _ = state
+            .relay_tx
+            .send((Some(client_id), room, frame, Instant::now()));
+    }
+    state.unregister(client_id);
+    debug!("connection closed");
+}
+
+// Sends a message from history, along with its reactions
+async fn send_stored(writer: &mut ClientWriter, message: StoredMessage) {
+    let reactions = message.to_reactions();
+    let _ = writer
+        .send(ServerFrame::receive(message.to_received()))
+        .await;
+    if !reactions.is_empty() {
+        let _ = writer
+            .send(ServerFrame::reactions(message.id, reactions))
+            .await;
+    }
+}
+
+// Sends `nick` the private messages and mentions that arrived while they were
+// offline, after saying how many there are
+async fn send_waiting(state: &State, writer: &mut ClientWriter, nick: &str) {
+    let waiting = state.mailbox.lock().unwrap().take(nick);
+    if waiting.is_empty() {
+        return;
+    }
+    let direct = waiting
+        .iter()
+        .filter(|item| matches!(item, mailbox::Queued::Direct { .. }))
+        .count();
+    let _ = writer
+        .send(ServerFrame::offline(direct, waiting.len() - direct))
+        .await;
+    for item in waiting {
+        let _ = writer.send(item.to_frame()).await;
+    }
+}
+
+// How far everyone else in `room` has read, if it is small enough to say
+fn seen_by(state: &State, nick: &str, room: &str) -> Vec<ServerFrame> {
+    let members = state.members(room);
+    if members.len() > state.config().limits.seen_by_max_members {
+        return Vec::new();
+    }
+    let reads = state.reads.lock().unwrap();
+    members
+        .into_iter()
+        .filter(|member| !same_nick(member, nick))
+        .filter_map(|member| {
+            let id = reads.get(&member, room)?;
+            Some(ServerFrame::seen(member, id))
+        })
+        .collect()
+}
+
+async fn report_message_error(writer: &mut ClientWriter, e: MessageError) {
+    let _ = writer.send(ServerFrame::error(e.to_string())).await;
+}
+
+async fn report_moderation(writer: &mut ClientWriter, result: Result<(), ModerationError>) {
+    if let Err(e) = result {
+        let _ = writer.send(ServerFrame::error(e.to_string())).await;
+    }
 }
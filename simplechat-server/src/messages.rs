@@ -0,0 +1,781 @@
+/// Messages relayed in each room, kept in memory and appended to a file so
+/// they survive restarts
+///
+/// The file holds one JSON record per line: every message as it was sent,
+/// followed by any later changes to it. Changes are kept in memory until
+/// `flush` writes them out in the background, which also rewrites the file
+/// from memory when it has grown well beyond what is being kept, and after a
+/// deletion so the deleted text doesn't linger on disk.
+use crate::{
+    moderation::Role,
+    state::{RoomName, State},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use simplechat_protocol::{same_nick, MessageId, Reaction, ReceivedMessage, SentMessage};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{error, info};
+
+/// A message as the server keeps it
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StoredMessage {
+    pub id: MessageId,
+    pub room: RoomName,
+    pub author: String,
+    pub text: String,
+    pub ts: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<StoredReaction>,
+    /// Kept so that a client sending the message again after losing the
+    /// connection doesn't store it twice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+}
+
+/// Everyone who reacted to a message with one emoji, in the order they did
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StoredReaction {
+    pub emoji: String,
+    pub nicks: Vec<String>,
+}
+
+impl StoredMessage {
+    pub fn to_received(&self) -> ReceivedMessage {
+        ReceivedMessage {
+            id: self.id,
+            author: self.author.clone(),
+            text: self.text.clone(),
+            ts: self.ts.clone(),
+            edited: self.edited.clone(),
+            reply_to: self.reply_to,
+            thread: self.thread,
+        }
+    }
+
+    pub fn to_reactions(&self) -> Vec<Reaction> {
+        self.reactions
+            .iter()
+            .map(|reaction| Reaction {
+                emoji: reaction.emoji.clone(),
+                nicks: reaction.nicks.clone(),
+            })
+            .collect()
+    }
+
+    // Adds `nick`'s reaction, or takes it away if they already reacted with
+    // `emoji`
+    fn toggle_reaction(&mut self, emoji: String, nick: String) {
+        let index = match self.reactions.iter().position(|r| r.emoji == emoji) {
+            Some(index) => index,
+            None => {
+                self.reactions.push(StoredReaction {
+                    emoji,
+                    nicks: Vec::new(),
+                });
+                self.reactions.len() - 1
+            }
+        };
+        let nicks = &mut self.reactions[index].nicks;
+        match nicks.iter().position(|other| same_nick(other, &nick)) {
+            Some(position) => {
+                nicks.remove(position);
+            }
+            None => nicks.push(nick),
+        }
+        if nicks.is_empty() {
+            self.reactions.remove(index);
+        }
+    }
+}
+
+/// A line in the message file
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Send(StoredMessage),
+    Edit {
+        id: MessageId,
+        text: String,
+        ts: String,
+    },
+    Delete {
+        id: MessageId,
+    },
+    React {
+        id: MessageId,
+        emoji: String,
+        nick: String,
+    },
+}
+
+/// Reasons a client can't change a message
+#[derive(Debug, Error)]
+pub enum MessageError {
+    #[error("join a room first")]
+    NotJoined,
+    #[error("there is no message {0} in this room")]
+    NotFound(MessageId),
+    #[error("you can only change your own messages")]
+    NotAuthor,
+    #[error("messages can only be edited for {}s after sending", .0.as_secs())]
+    TooLate(Duration),
+    #[error("messages can't be empty")]
+    Empty,
+    #[error("{0:?} isn't a reaction")]
+    InvalidReaction(String),
+}
+
+#[derive(Debug)]
+pub struct MessageStore {
+    path: PathBuf,
+    /// The most recent messages in each room, oldest first
+    rooms: HashMap<RoomName, VecDeque<StoredMessage>>,
+    /// How many messages to keep per room
+    limit: usize,
+    next_id: MessageId,
+    /// Lines in the file and waiting to be written, which includes trimmed
+    /// messages and changes
+    records: usize,
+    /// Records applied in memory but not yet written to the file
+    unwritten: Vec<String>,
+    /// Whether the file still holds the text of deleted messages
+    deleted: bool,
+}
+
+impl MessageStore {
+    /// Loads messages from `path`. A missing file is an empty store.
+    pub fn load(path: impl Into<PathBuf>, limit: usize) -> Result<Self> {
+        let mut store = Self {
+            path: path.into(),
+            rooms: HashMap::new(),
+            limit,
+            next_id: 1,
+            records: 0,
+            unwritten: Vec::new(),
+            deleted: false,
+        };
+        let contents = match fs::read_to_string(&store.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", store.path.display()))
+            }
+        };
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(line).with_context(|| {
+                format!(
+                    "{} line {}: invalid record",
+                    store.path.display(),
+                    number + 1
+                )
+            })?;
+            // The server may have stopped before compacting away a deletion
+            store.deleted |= matches!(record, Record::Delete { .. });
+            store.apply(record);
+            store.records += 1;
+        }
+        info!(
+            path = %store.path.display(),
+            messages = store.len(),
+            "loaded messages"
+        );
+        if store.oversized() {
+            if let Err(e) = store.compact() {
+                error!(path = %store.path.display(), "failed to compact messages: {:#}", e);
+            }
+        }
+        Ok(store)
+    }
+
+    /// Stores a new message from `author` in `room`, assigning its ID and
+    /// timestamp
+    pub fn add(&mut self, room: &str, author: &str, message: SentMessage) -> StoredMessage {
+        let message = StoredMessage {
+            id: self.next_id,
+            room: room.to_string(),
+            author: author.to_string(),
+            text: message.text,
+            ts: now(),
+            edited: None,
+            reply_to: message.reply_to,
+            thread: message.thread,
+            reactions: Vec::new(),
+            nonce: message.nonce,
+        };
+        self.append(Record::Send(message.clone()));
+        message
+    }
+
+    /// Finds message `id`, as long as it was sent in `room`
+    pub fn get(&self, room: &str, id: MessageId) -> Option<&StoredMessage> {
+        let messages = self.rooms.get(room)?;
+        let index = messages
+            .binary_search_by_key(&id, |message| message.id)
+            .ok()?;
+        messages.get(index)
+    }
+
+    /// Finds the message `author` sent to `room` with `nonce`
+    pub fn get_by_nonce(&self, room: &str, author: &str, nonce: &str) -> Option<&StoredMessage> {
+        self.rooms.get(room)?.iter().rev().find(|message| {
+            message.nonce.as_deref() == Some(nonce) && same_nick(&message.author, author)
+        })
+    }
+
+    /// Replaces the text of message `id` in `room`, returning the message as
+    /// changed
+    pub fn edit(&mut self, room: &str, id: MessageId, text: &str) -> Option<StoredMessage> {
+        self.get(room, id)?;
+        self.append(Record::Edit {
+            id,
+            text: text.to_string(),
+            ts: now(),
+        });
+        self.get(room, id).cloned()
+    }
+
+    /// Removes message `id` from `room`, returning it. Its text stays in the
+    /// file until `flush` next runs.
+    pub fn delete(&mut self, room: &str, id: MessageId) -> Option<StoredMessage> {
+        let message = self.get(room, id)?.clone();
+        self.append(Record::Delete { id });
+        self.deleted = true;
+        Some(message)
+    }
+
+    /// Writes out changes made since last time. The file is rewritten
+    /// instead if it holds the text of deleted messages or has grown well
+    /// beyond what is kept. Files are written without holding the lock on
+    /// `store`, and only from here once the store is loaded, so calls must not
+    /// overlap.
+    pub fn flush(store: &Mutex<Self>) {
+        let (path, lines, compacted) = {
+            let mut store = store.lock().unwrap();
+            let lines = std::mem::take(&mut store.unwritten);
+            let compacted = if store.deleted || store.oversized() {
+                // Set again by any deletion made while this is written
+                store.deleted = false;
+                Some(store.compacted())
+            } else {
+                None
+            };
+            (store.path.clone(), lines, compacted)
+        };
+        // The rewritten file includes everything in `lines`, so they only
+        // need appending if it couldn't be written
+        if let Some(contents) = compacted {
+            let written = contents.and_then(|contents| {
+                replace_file(&path, &contents)?;
+                Ok(contents.lines().count())
+            });
+            match written {
+                Ok(records) => {
+                    let mut store = store.lock().unwrap();
+                    store.records = records + store.unwritten.len();
+                    return;
+                }
+                Err(e) => {
+                    error!(path = %path.display(), "failed to compact messages: {:#}", e);
+                    store.lock().unwrap().deleted = true;
+                }
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(lines.concat().as_bytes()));
+        if let Err(e) = result {
+            // Kept to try again next time, ahead of anything since
+            error!(path = %path.display(), "failed to store messages: {}", e);
+            store.lock().unwrap().unwritten.splice(0..0, lines);
+        }
+    }
+
+    /// Toggles `nick`'s reaction to message `id` in `room`, returning the
+    /// message as changed
+    pub fn react(
+        &mut self,
+        room: &str,
+        id: MessageId,
+        emoji: &str,
+        nick: &str,
+    ) -> Option<StoredMessage> {
+        self.get(room, id)?;
+        self.append(Record::React {
+            id,
+            emoji: emoji.to_string(),
+            nick: nick.to_string(),
+        });
+        self.get(room, id).cloned()
+    }
+
+    /// The first message of the thread `id` belongs to in `room`, followed by
+    /// the rest of the thread, or `None` if there is no such message
+    pub fn thread(&self, room: &str, id: MessageId) -> Option<Vec<StoredMessage>> {
+        let root = self.get(room, id)?;
+        let root = root.thread.unwrap_or(root.id);
+        let first = self.get(room, root)?;
+        let rest = self.rooms[room]
+            .iter()
+            .filter(|message| message.thread == Some(root));
+        Some(std::iter::once(first).chain(rest).cloned().collect())
+    }
+
+    /// Up to `count` of the latest messages in `room`, oldest first
+    pub fn recent(&self, room: &str, count: usize) -> Vec<StoredMessage> {
+        let Some(messages) = self.rooms.get(room) else {
+            return Vec::new();
+        };
+        let skip = messages.len().saturating_sub(count);
+        messages.iter().skip(skip).cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.rooms.values().map(VecDeque::len).sum()
+    }
+
+    // Updates the messages in memory
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Send(message) => {
+                self.next_id = self.next_id.max(message.id + 1);
+                let messages = self.rooms.entry(message.room.clone()).or_default();
+                messages.push_back(message);
+                if messages.len() > self.limit {
+                    messages.pop_front();
+                }
+            }
+            Record::Edit { id, text, ts } => {
+                if let Some(message) = self.find_mut(id) {
+                    message.text = text;
+                    message.edited = Some(ts);
+                }
+            }
+            Record::React { id, emoji, nick } => {
+                if let Some(message) = self.find_mut(id) {
+                    message.toggle_reaction(emoji, nick);
+                }
+            }
+            Record::Delete { id } => {
+                self.next_id = self.next_id.max(id + 1);
+                for messages in self.rooms.values_mut() {
+                    messages.retain(|message| message.id != id);
+                }
+            }
+        }
+    }
+
+    fn find_mut(&mut self, id: MessageId) -> Option<&mut StoredMessage> {
+        self.rooms
+            .values_mut()
+            .find_map(|messages| messages.iter_mut().find(|message| message.id == id))
+    }
+
+    // Applies `record` and queues it to be written to the end of the file
+    fn append(&mut self, record: Record) {
+        let line = serde_json::to_string(&record).expect("records always serialize");
+        self.apply(record);
+        self.unwritten.push(line + "\n");
+        self.records += 1;
+    }
+
+    // Whether most of the file is no longer needed
+    fn oversized(&self) -> bool {
+        self.records > 2 * self.len() + 1000
+    }
+
+    // Replaces the file with one holding only the messages in memory
+    fn compact(&mut self) -> Result<()> {
+        let contents = self.compacted()?;
+        replace_file(&self.path, &contents)?;
+        self.records = contents.lines().count();
+        self.unwritten.clear();
+        self.deleted = false;
+        Ok(())
+    }
+
+    // The file as it would be holding only the messages in memory
+    fn compacted(&self) -> Result<String> {
+        let mut messages: Vec<_> = self.rooms.values().flatten().collect();
+        messages.sort_by_key(|message| message.id);
+        let mut contents = String::new();
+        for message in &messages {
+            contents += &serde_json::to_string(&Record::Send((*message).clone()))?;
+            contents.push('\n');
+        }
+        // Keeps IDs of deleted messages from being handed out again
+        let last = messages.last().map_or(0, |message| message.id);
+        if self.next_id > last + 1 {
+            let id = self.next_id - 1;
+            contents += &serde_json::to_string(&Record::Delete { id })?;
+            contents.push('\n');
+        }
+        Ok(contents)
+    }
+}
+
+// Writes `contents` beside `path` and renames it over the file, so a crash
+// can't leave it half written
+fn replace_file(path: &Path, contents: &str) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents)
+        .and_then(|_| fs::rename(&temp, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Replaces the text of message `id` on behalf of `nick`, who must have sent it
+/// recently enough
+pub fn edit(
+    state: &State,
+    nick: &str,
+    room: Option<&str>,
+    id: MessageId,
+    text: &str,
+) -> Result<StoredMessage, MessageError> {
+    let room = room.ok_or(MessageError::NotJoined)?;
+    if text.trim().is_empty() {
+        return Err(MessageError::Empty);
+    }
+    let window = Duration::from_secs(state.config().limits.edit_window_secs);
+    let mut messages = state.messages.lock().unwrap();
+    let message = messages.get(room, id).ok_or(MessageError::NotFound(id))?;
+    if !same_nick(&message.author, nick) {
+        return Err(MessageError::NotAuthor);
+    }
+    if age(&message.ts) > window {
+        return Err(MessageError::TooLate(window));
+    }
+    Ok(messages
+        .edit(room, id, text)
+        .expect("message was just found"))
+}
+
+/// Stores a message from `nick`, after checking that any message it replies
+/// to or thread it is posted in is in the same room. Also returns whether the
+/// message is new, rather than sent again with the nonce of one stored before.
+pub fn send(
+    state: &State,
+    nick: &str,
+    room: Option<&str>,
+    mut message: SentMessage,
+) -> Result<(StoredMessage, bool), MessageError> {
+    let room = room.ok_or(MessageError::NotJoined)?;
+    let mut messages = state.messages.lock().unwrap();
+    if let Some(nonce) = &message.nonce {
+        if let Some(stored) = messages.get_by_nonce(room, nick, nonce) {
+            return Ok((stored.clone(), false));
+        }
+    }
+    if let Some(parent) = message.reply_to {
+        messages
+            .get(room, parent)
+            .ok_or(MessageError::NotFound(parent))?;
+    }
+    if let Some(root) = message.thread {
+        let root = messages
+            .get(room, root)
+            .ok_or(MessageError::NotFound(root))?;
+        // Posting under a message that is itself in a thread continues that
+        // thread rather than starting another
+        message.thread = Some(root.thread.unwrap_or(root.id));
+    }
+    Ok((messages.add(room, nick, message), true))
+}
+
+/// The messages in thread `root`, starting with the one that began it
+pub fn thread(
+    state: &State,
+    room: Option<&str>,
+    root: MessageId,
+) -> Result<Vec<StoredMessage>, MessageError> {
+    let room = room.ok_or(MessageError::NotJoined)?;
+    let messages = state.messages.lock().unwrap();
+    messages
+        .thread(room, root)
+        .ok_or(MessageError::NotFound(root))
+}
+
+/// Removes message `id` on behalf of `nick`, who must have sent it or be a
+/// moderator with a higher role than its author
+pub fn delete(
+    state: &State,
+    nick: &str,
+    room: Option<&str>,
+    id: MessageId,
+) -> Result<StoredMessage, MessageError> {
+    let room = room.ok_or(MessageError::NotJoined)?;
+    let mut messages = state.messages.lock().unwrap();
+    let message = messages.get(room, id).ok_or(MessageError::NotFound(id))?;
+    let own = same_nick(&message.author, nick);
+    if !own {
+        let role = state.role(nick);
+        if role < Role::Moderator || state.role(&message.author) >= role {
+            return Err(MessageError::NotAuthor);
+        }
+        let detail = format!("message {} in {}", id, room);
+        state
+            .modlog
+            .record(nick, "delete", &message.author, &detail);
+    }
+    Ok(messages.delete(room, id).expect("message was just found"))
+}
+
+/// Toggles `nick`'s reaction to message `id`. Shortcodes like `:tada:` are
+/// turned into the emoji they stand for.
+pub fn react(
+    state: &State,
+    nick: &str,
+    room: Option<&str>,
+    id: MessageId,
+    emoji: &str,
+) -> Result<StoredMessage, MessageError> {
+    let room = room.ok_or(MessageError::NotJoined)?;
+    let emoji =
+        reaction_emoji(emoji).ok_or_else(|| MessageError::InvalidReaction(emoji.to_string()))?;
+    state
+        .messages
+        .lock()
+        .unwrap()
+        .react(room, id, emoji, nick)
+        .ok_or(MessageError::NotFound(id))
+}
+
+// The single emoji `reaction` stands for, whether typed as one or as a GitHub
+// `:shortcode:`. Variants of the same emoji come out the same.
+fn reaction_emoji(reaction: &str) -> Option<&'static str> {
+    let reaction = reaction.trim();
+    let emoji = match reaction.strip_prefix(':').and_then(|s| s.strip_suffix(':')) {
+        Some(shortcode) => emojis::get_by_shortcode(shortcode),
+        None => emojis::get(reaction),
+    };
+    emoji.map(|emoji| emoji.as_str())
+}
+
+/// The current time as it appears in frames and on disk
+pub fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+// How long ago `ts` was, treating unreadable timestamps as long ago
+fn age(ts: &str) -> Duration {
+    OffsetDateTime::parse(ts, &Rfc3339)
+        .map(|sent| (OffsetDateTime::now_utc() - sent).unsigned_abs())
+        .unwrap_or(Duration::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{reaction_emoji, MessageStore};
+    use simplechat_protocol::SentMessage;
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    };
+    use tempfile::TempDir;
+
+    fn store(dir: &TempDir) -> (PathBuf, MessageStore) {
+        let path = dir.path().join("messages.jsonl");
+        let store = MessageStore::load(&path, 2).unwrap();
+        (path, store)
+    }
+
+    // Writes out `store` and loads the file again
+    fn reload(store: MessageStore, path: &Path) -> MessageStore {
+        MessageStore::flush(&Mutex::new(store));
+        MessageStore::load(path, 2).unwrap()
+    }
+
+    #[test]
+    fn test_keeps_latest_per_room() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        let first = store.add(
+            "lab",
+            "Reed",
+            SentMessage::new("Reed", "Unstable molecules"),
+        );
+        store.add("lab", "Sue", SentMessage::new("Sue", "Again?"));
+        let lobby = store.add("lobby", "Ben", SentMessage::new("Ben", "Clobbering time"));
+        let last = store.add("lab", "Reed", SentMessage::new("Reed", "Negative zone"));
+        assert!(store.get("lab", first.id).is_none(), "trimmed to the limit");
+        assert!(store.get("lobby", last.id).is_none(), "wrong room");
+
+        let reloaded = reload(store, &path);
+        let texts: Vec<_> = reloaded
+            .recent("lab", 10)
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        assert_eq!(texts, ["Again?", "Negative zone"]);
+        assert_eq!(reloaded.recent("lobby", 10), [lobby]);
+        assert_eq!(reloaded.next_id, last.id + 1);
+    }
+
+    #[test]
+    fn test_edits_survive_reload() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        let message = store.add("lab", "Reed", SentMessage::new("Reed", "Negative zone"));
+        store.edit("lab", message.id, "Negative Zone").unwrap();
+        assert!(store.edit("lobby", message.id, "Wrong room").is_none());
+
+        let reloaded = reload(store, &path);
+        let edited = reloaded.get("lab", message.id).unwrap();
+        assert_eq!(edited.text, "Negative Zone");
+        assert!(edited.edited.is_some());
+    }
+
+    #[test]
+    fn test_reactions_toggle() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        let message = store.add("lab", "Reed", SentMessage::new("Reed", "Negative zone"));
+        store.react("lab", message.id, "👍", "Sue").unwrap();
+        store.react("lab", message.id, "🎉", "Ben").unwrap();
+        store.react("lab", message.id, "🎉", "ben").unwrap();
+        store.react("lab", message.id, "👍", "Ångström").unwrap();
+        store.react("lab", message.id, "👍", "ÅNGSTRÖM").unwrap();
+
+        let reloaded = reload(store, &path);
+        let reactions = reloaded.get("lab", message.id).unwrap().to_reactions();
+        assert_eq!(reactions.len(), 1, "second reaction was toggled off");
+        assert_eq!(reactions[0].emoji, "👍");
+        assert_eq!(reactions[0].nicks, ["Sue"]);
+    }
+
+    #[test]
+    fn test_reaction_emoji() {
+        assert_eq!(reaction_emoji("👍"), Some("👍"));
+        assert_eq!(reaction_emoji(" :tada: "), Some("🎉"));
+        assert_eq!(reaction_emoji(":+1:"), Some("👍"));
+        assert_eq!(reaction_emoji("👍🏽"), Some("👍🏽"));
+        assert_eq!(
+            reaction_emoji("👨\u{200d}👩\u{200d}👧"),
+            Some("👨\u{200d}👩\u{200d}👧")
+        );
+        assert_eq!(reaction_emoji("❤"), reaction_emoji("❤\u{fe0f}"));
+        assert_eq!(reaction_emoji("+1"), None);
+        assert_eq!(reaction_emoji("lol"), None);
+        assert_eq!(reaction_emoji("👍👍"), None);
+        assert_eq!(reaction_emoji(":not_a_shortcode:"), None);
+        assert_eq!(reaction_emoji(""), None);
+    }
+
+    #[test]
+    fn test_finds_resent_messages_by_nonce() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        let sent = SentMessage::new("Ben", "Clobbering time").with_nonce("n1");
+        let sent = store.add("lobby", "Ben", sent);
+
+        let reloaded = reload(store, &path);
+        let found = reloaded.get_by_nonce("lobby", "ben", "n1").unwrap();
+        assert_eq!(found.id, sent.id);
+        assert!(reloaded.get_by_nonce("lobby", "Sue", "n1").is_none());
+        assert!(reloaded.get_by_nonce("lab", "Ben", "n1").is_none());
+    }
+
+    #[test]
+    fn test_deleted_ids_are_not_reused() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        store.add("lobby", "Ben", SentMessage::new("Ben", "Clobbering time"));
+        let deleted = store.add("lobby", "Ben", SentMessage::new("Ben", "Not again"));
+        store.delete("lobby", deleted.id).unwrap();
+        assert!(store.get("lobby", deleted.id).is_none());
+        assert!(store.delete("lobby", deleted.id).is_none());
+
+        let reloaded = reload(store, &path);
+        assert!(reloaded.get("lobby", deleted.id).is_none());
+        assert_eq!(reloaded.next_id, deleted.id + 1);
+    }
+
+    #[test]
+    fn test_written_only_when_flushed() {
+        let dir = TempDir::new().unwrap();
+        let (path, mut store) = store(&dir);
+        store.add("lobby", "Ben", SentMessage::new("Ben", "Clobbering time"));
+        assert!(!path.exists());
+
+        let store = Mutex::new(store);
+        MessageStore::flush(&store);
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("Clobbering time"));
+        store
+            .lock()
+            .unwrap()
+            .add("lobby", "Ben", SentMessage::new("Ben", "Again"));
+        MessageStore::flush(&store);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2, "appended, not rewritten");
+    }
+
+    #[test]
+    fn test_deleted_text_is_compacted_away() {
+        let dir = TempDir::new().unwrap();
+        let (path, store) = store(&dir);
+        let store = Mutex::new(store);
+        let (kept, deleted) = {
+            let mut store = store.lock().unwrap();
+            let kept = store.add("lobby", "Ben", SentMessage::new("Ben", "Clobbering time"));
+            let deleted = store.add("lobby", "Ben", SentMessage::new("Ben", "Not again"));
+            (kept, deleted)
+        };
+        MessageStore::flush(&store);
+        assert!(fs::read_to_string(&path).unwrap().contains("Not again"));
+
+        store.lock().unwrap().delete("lobby", deleted.id).unwrap();
+        MessageStore::flush(&store);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("Not again"));
+        assert!(contents.contains("Clobbering time"));
+        let reloaded = MessageStore::load(&path, 2).unwrap();
+        assert_eq!(reloaded.recent("lobby", 10), [kept]);
+        assert_eq!(reloaded.next_id, deleted.id + 1);
+        assert!(!store.lock().unwrap().deleted);
+    }
+
+    #[test]
+    fn test_thread() {
+        let dir = TempDir::new().unwrap();
+        let mut store = MessageStore::load(dir.path().join("messages.jsonl"), 10).unwrap();
+        let root = store.add("lab", "Reed", SentMessage::new("Reed", "Negative zone"));
+        store.add("lab", "Ben", SentMessage::new("Ben", "Not in the thread"));
+        let reply = SentMessage::new("Sue", "Not again").in_thread(root.id);
+        let reply = store.add("lab", "Sue", reply);
+        let thread: Vec<_> = store
+            .thread("lab", reply.id)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.id)
+            .collect();
+        assert_eq!(thread, [root.id, reply.id]);
+        assert!(store.thread("lobby", root.id).is_none());
+    }
+}
@@ -0,0 +1,339 @@
+/// Server statistics, exported in the Prometheus text format on `/metrics`
+use crate::{http::Response, state::State};
+use simplechat_protocol::Error;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::codec::LinesCodecError;
+
+/// Upper bounds of the relay latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Why a frame from a client was rejected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameError {
+    TooLong,
+    Invalid,
+}
+
+impl FrameError {
+    const ALL: [FrameError; 2] = [FrameError::TooLong, FrameError::Invalid];
+
+    /// Works out why decoding failed, or `None` if the connection itself
+    /// failed rather than the client sending something bad
+    pub fn classify(e: &Error) -> Option<Self> {
+        match e {
+            Error::LinesParseError(LinesCodecError::MaxLineLengthExceeded) => {
+                Some(FrameError::TooLong)
+            }
+            Error::IoError(e) | Error::LinesParseError(LinesCodecError::Io(e))
+                if e.kind() != io::ErrorKind::InvalidData =>
+            {
+                None
+            }
+            _ => Some(FrameError::Invalid),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FrameError::TooLong => "too_long",
+            FrameError::Invalid => "invalid",
+        }
+    }
+}
+
+/// Counts of observations falling at or below each of `LATENCY_BUCKETS`
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counters reported through the admin socket and `/metrics`
+#[derive(Debug)]
+pub struct Stats {
+    pub started: Instant,
+    pub accepted: AtomicU64,
+    pub rejected: AtomicU64,
+    pub relayed: AtomicU64,
+    pub bytes_in: AtomicU64,
+    pub bytes_out: AtomicU64,
+    frames_rejected: [AtomicU64; FrameError::ALL.len()],
+    /// Times a client fell so far behind that relayed messages were dropped
+    pub lag_events: AtomicU64,
+    /// Messages dropped because clients fell behind
+    pub lagged_messages: AtomicU64,
+    /// Time between relaying a message and writing it to a recipient
+    pub relay_latency: Histogram,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            accepted: AtomicU64::default(),
+            rejected: AtomicU64::default(),
+            relayed: AtomicU64::default(),
+            bytes_in: AtomicU64::default(),
+            bytes_out: AtomicU64::default(),
+            frames_rejected: Default::default(),
+            lag_events: AtomicU64::default(),
+            lagged_messages: AtomicU64::default(),
+            relay_latency: Histogram::default(),
+        }
+    }
+}
+
+impl Stats {
+    pub fn frame_rejected(&self, error: FrameError) {
+        let index = FrameError::ALL.iter().position(|e| *e == error).unwrap();
+        self.frames_rejected[index].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Wraps a client's stream to count the bytes passing through it
+#[derive(Debug)]
+pub struct CountingStream<S> {
+    inner: S,
+    stats: Arc<Stats>,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, stats: Arc<Stats>) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        self.stats
+            .bytes_in
+            .fetch_add(read as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.stats
+                .bytes_out
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Serves `/metrics`
+pub fn route(state: &State, path: &str) -> Option<Response> {
+    (path == "/metrics").then(|| {
+        Response::new(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(state),
+        )
+    })
+}
+
+// Writes the current values of every metric
+fn render(state: &State) -> String {
+    let stats = &state.stats;
+    let clients = state.clients();
+    let mut members: BTreeMap<String, u64> = BTreeMap::new();
+    for (_, handle) in &clients {
+        if let Some(room) = &handle.room {
+            *members.entry(room.clone()).or_default() += 1;
+        }
+    }
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed).to_string();
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP simplechat_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE simplechat_{} {}", name, kind);
+        for (suffix, value) in samples {
+            let _ = writeln!(out, "simplechat_{}{} {}", name, suffix, value);
+        }
+    };
+    let single = |value: String| vec![(String::new(), value)];
+
+    family(
+        "uptime_seconds",
+        "gauge",
+        "Seconds since the server started",
+        single(stats.started.elapsed().as_secs().to_string()),
+    );
+    family(
+        "connected_clients",
+        "gauge",
+        "Currently open client connections",
+        single(clients.len().to_string()),
+    );
+    family(
+        "connections_accepted_total",
+        "counter",
+        "Client connections accepted",
+        single(load(&stats.accepted)),
+    );
+    family(
+        "connections_rejected_total",
+        "counter",
+        "Client connections turned away",
+        single(load(&stats.rejected)),
+    );
+    family(
+        "messages_relayed_total",
+        "counter",
+        "Chat messages relayed to rooms",
+        single(load(&stats.relayed)),
+    );
+    family(
+        "received_bytes_total",
+        "counter",
+        "Bytes read from clients",
+        single(load(&stats.bytes_in)),
+    );
+    family(
+        "sent_bytes_total",
+        "counter",
+        "Bytes written to clients",
+        single(load(&stats.bytes_out)),
+    );
+    family(
+        "frames_rejected_total",
+        "counter",
+        "Frames from clients that could not be decoded",
+        FrameError::ALL
+            .iter()
+            .zip(&stats.frames_rejected)
+            .map(|(error, count)| (format!("{{kind=\"{}\"}}", error.label()), load(count)))
+            .collect(),
+    );
+    family(
+        "broadcast_lag_events_total",
+        "counter",
+        "Times a client fell behind and missed relayed messages",
+        single(load(&stats.lag_events)),
+    );
+    family(
+        "broadcast_lagged_messages_total",
+        "counter",
+        "Relayed messages missed by clients that fell behind",
+        single(load(&stats.lagged_messages)),
+    );
+    family(
+        "room_members",
+        "gauge",
+        "Clients currently joined to each room",
+        members
+            .iter()
+            .map(|(room, count)| {
+                (
+                    format!("{{room=\"{}\"}}", escape_label(room)),
+                    count.to_string(),
+                )
+            })
+            .collect(),
+    );
+
+    let latency = &stats.relay_latency;
+    let count = load(&latency.count);
+    let mut samples: Vec<_> = latency
+        .buckets
+        .iter()
+        .zip(LATENCY_BUCKETS)
+        .map(|(bucket, bound)| (format!("_bucket{{le=\"{}\"}}", bound), load(bucket)))
+        .collect();
+    samples.push((String::from("_bucket{le=\"+Inf\"}"), count.clone()));
+    let sum = latency.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+    samples.push((String::from("_sum"), sum.to_string()));
+    samples.push((String::from("_count"), count));
+    family(
+        "relay_latency_seconds",
+        "histogram",
+        "Time from relaying a message to writing it to a recipient",
+        samples,
+    );
+    out
+}
+
+// Escapes a label value as the exposition format requires
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_label, Histogram};
+    use std::{sync::atomic::Ordering, time::Duration};
+
+    #[test]
+    fn test_histogram_and_labels() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(300));
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(3));
+        let buckets: Vec<_> = histogram
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(buckets, [1, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(histogram.count.load(Ordering::Relaxed), 3);
+        assert_eq!(histogram.sum_micros.load(Ordering::Relaxed), 3_020_300);
+
+        assert_eq!(escape_label(r#"the "lab""#), r#"the \"lab\""#);
+        assert_eq!(escape_label("a\\b\nc"), "a\\\\b\\nc");
+    }
+}
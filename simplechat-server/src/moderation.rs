@@ -0,0 +1,453 @@
+/// Moderator roles and the kick, mute and ban actions available to them
+use crate::{
+    bans::Ban,
+    state::{ClientHandle, Control, State},
+};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet, fmt, fs::OpenOptions, io::Write, path::PathBuf, str::FromStr,
+    time::Duration,
+};
+use thiserror::Error;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::error;
+
+/// The widest IPv4 and IPv6 prefixes a moderator may ban, as broader ranges
+/// catch too many bystanders for anyone but an operator to decide on
+const MODERATOR_MAX_RANGE: (u8, u8) = (16, 48);
+
+/// Authority a user has over others, in increasing order
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    /// May kick, mute and ban users
+    #[serde(alias = "mod")]
+    Moderator,
+    /// May additionally grant and revoke roles
+    #[serde(alias = "op")]
+    Operator,
+}
+
+impl FromStr for Role {
+    type Err = ModerationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "user" => Ok(Role::User),
+            "mod" | "moderator" => Ok(Role::Moderator),
+            "op" | "operator" => Ok(Role::Operator),
+            _ => Err(ModerationError::UnknownRole(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Moderator => write!(f, "moderator"),
+            Role::Operator => write!(f, "operator"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ModerationError {
+    #[error("join a room first")]
+    NotJoined,
+
+    #[error("you are not allowed to do that")]
+    NotPermitted,
+
+    #[error("{0} is not connected")]
+    NotConnected(String),
+
+    #[error("{0} is not banned")]
+    NotBanned(String),
+
+    #[error("{0} is not a registered user")]
+    NotRegistered(String),
+
+    #[error("unknown role {0:?}")]
+    UnknownRole(String),
+
+    #[error("that mute is too long")]
+    MuteTooLong,
+
+    #[error("you are muted for another {}s", .0.as_secs() + 1)]
+    Muted(Duration),
+
+    #[error("failed to update ban list")]
+    BanList(#[source] anyhow::Error),
+}
+
+/// The user performing a moderation action
+#[derive(Debug)]
+pub struct Actor<'a> {
+    pub nick: &'a str,
+    /// Room the action is announced in, besides the rooms of those affected
+    pub room: Option<&'a str>,
+    admin: bool,
+}
+
+impl<'a> Actor<'a> {
+    /// Moderation requires having joined, so the action can be announced
+    pub fn joined(nick: &'a str, room: Option<&'a str>) -> Result<Self, ModerationError> {
+        let room = room.ok_or(ModerationError::NotJoined)?;
+        Ok(Self {
+            nick,
+            room: Some(room),
+            admin: false,
+        })
+    }
+
+    /// Whoever is operating the server through the admin socket, who is
+    /// allowed to do anything
+    pub fn admin() -> Self {
+        Self {
+            nick: "the server admin",
+            room: None,
+            admin: true,
+        }
+    }
+}
+
+/// Append-only record of moderation actions, one tab-separated line each
+#[derive(Debug)]
+pub struct ModerationLog {
+    path: PathBuf,
+}
+
+impl ModerationLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn record(&self, actor: &str, action: &str, target: &str, detail: &str) {
+        let ts = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        let line = format!("{}\t{}\t{}\t{}\t{}\n", ts, actor, action, target, detail);
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = result {
+            error!(path = %self.path.display(), "failed to write moderation log: {}", e);
+        }
+    }
+}
+
+/// Disconnects every connection using `nick`
+pub fn kick(state: &State, actor: &Actor, nick: &str, reason: &str) -> Result<(), ModerationError> {
+    authorize(state, actor, Role::Moderator, Some(nick))?;
+    let targets = state.find_by_nick(nick);
+    if targets.is_empty() {
+        return Err(ModerationError::NotConnected(nick.to_string()));
+    }
+    let message = with_reason(format!("you were kicked by {}", actor.nick), reason);
+    disconnect(&targets, &message);
+    let notice = with_reason(format!("{} was kicked by {}", nick, actor.nick), reason);
+    announce(state, actor, &targets, &notice);
+    state.modlog.record(actor.nick, "kick", nick, reason);
+    Ok(())
+}
+
+/// Stops relaying messages from `nick` for `secs` seconds, or lifts a mute
+/// when `secs` is zero
+pub fn mute(state: &State, actor: &Actor, nick: &str, secs: u64) -> Result<(), ModerationError> {
+    authorize(state, actor, Role::Moderator, Some(nick))?;
+    state.mute(nick, Duration::from_secs(secs))?;
+    let targets = state.find_by_nick(nick);
+    let (action, notice) = if secs == 0 {
+        ("unmute", format!("{} was unmuted by {}", nick, actor.nick))
+    } else {
+        let notice = format!("{} was muted by {} for {}s", nick, actor.nick, secs);
+        ("mute", notice)
+    };
+    announce(state, actor, &targets, &notice);
+    state
+        .modlog
+        .record(actor.nick, action, nick, &format!("{}s", secs));
+    Ok(())
+}
+
+/// Bans a nickname or address and disconnects anyone matching it. Banning an
+/// address needs authority over everyone connected from it, and ranges wider
+/// than `MODERATOR_MAX_RANGE` need an operator.
+pub fn ban(
+    state: &State,
+    actor: &Actor,
+    target: &str,
+    reason: &str,
+) -> Result<(), ModerationError> {
+    let ban = Ban::from_target(target);
+    let targets = match &ban {
+        Ban::Addr(net) => {
+            let (v4, v6) = MODERATOR_MAX_RANGE;
+            let wide = match net {
+                IpNet::V4(net) => net.prefix_len() < v4,
+                IpNet::V6(net) => net.prefix_len() < v6,
+            };
+            let required = if wide {
+                Role::Operator
+            } else {
+                Role::Moderator
+            };
+            authorize(state, actor, required, None)?;
+            let targets = state.find(|handle| net.contains(&handle.addr.ip()));
+            for handle in &targets {
+                authorize(state, actor, required, Some(&handle.nick))?;
+            }
+            targets
+        }
+        Ban::Nick(nick) => {
+            authorize(state, actor, Role::Moderator, Some(nick))?;
+            state.find_by_nick(nick)
+        }
+    };
+    state
+        .bans
+        .lock()
+        .unwrap()
+        .add(ban)
+        .map_err(ModerationError::BanList)?;
+    let message = with_reason(format!("you were banned by {}", actor.nick), reason);
+    disconnect(&targets, &message);
+    let notice = with_reason(format!("{} was banned by {}", target, actor.nick), reason);
+    announce(state, actor, &targets, &notice);
+    state.modlog.record(actor.nick, "ban", target, reason);
+    Ok(())
+}
+
+/// Lifts a ban on a nickname or address
+pub fn unban(state: &State, actor: &Actor, target: &str) -> Result<(), ModerationError> {
+    authorize(state, actor, Role::Moderator, None)?;
+    let removed = state
+        .bans
+        .lock()
+        .unwrap()
+        .remove(&Ban::from_target(target))
+        .map_err(ModerationError::BanList)?;
+    if !removed {
+        return Err(ModerationError::NotBanned(target.to_string()));
+    }
+    announce(
+        state,
+        actor,
+        &[],
+        &format!("{} was unbanned by {}", target, actor.nick),
+    );
+    state.modlog.record(actor.nick, "unban", target, "");
+    Ok(())
+}
+
+/// Grants registered user `nick` a role, or revokes it by setting the `user`
+/// role
+pub fn set_role(
+    state: &State,
+    actor: &Actor,
+    nick: &str,
+    role: &str,
+) -> Result<(), ModerationError> {
+    authorize(state, actor, Role::Operator, None)?;
+    let role = role.parse::<Role>()?;
+    if state.config().user(nick).is_none() {
+        return Err(ModerationError::NotRegistered(nick.to_string()));
+    }
+    state.set_role(nick, role);
+    let targets = state.find_by_nick(nick);
+    let notice = format!("{} is now a {} (set by {})", nick, role, actor.nick);
+    announce(state, actor, &targets, &notice);
+    state
+        .modlog
+        .record(actor.nick, "role", nick, &role.to_string());
+    Ok(())
+}
+
+// The actor needs at least `required`, and may only act on users with a
+// lower role than their own
+fn authorize(
+    state: &State,
+    actor: &Actor,
+    required: Role,
+    target: Option<&str>,
+) -> Result<(), ModerationError> {
+    if actor.admin {
+        return Ok(());
+    }
+    let role = state.role(actor.nick);
+    if role < required || target.is_some_and(|target| state.role(target) >= role) {
+        return Err(ModerationError::NotPermitted);
+    }
+    Ok(())
+}
+
+fn disconnect(targets: &[ClientHandle], message: &str) {
+    for handle in targets {
+        let _ = handle
+            .control
+            .send(Control::Disconnect(message.to_string()));
+    }
+}
+
+// Announces in the actor's room and every room the targets are in
+fn announce(state: &State, actor: &Actor, targets: &[ClientHandle], notice: &str) {
+    let rooms: BTreeSet<&str> = targets
+        .iter()
+        .filter_map(|handle| handle.room.as_deref())
+        .chain(actor.room)
+        .collect();
+    for room in rooms {
+        state.announce(room, notice);
+    }
+}
+
+fn with_reason(text: String, reason: &str) -> String {
+    if reason.is_empty() {
+        text
+    } else {
+        format!("{} ({})", text, reason)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ban, unban, Actor, ModerationError, ModerationLog, Role};
+    use crate::{
+        bans::BanList,
+        config::{Config, ConfigSource, UserConfig},
+        mailbox::Mailbox,
+        messages::MessageStore,
+        reads::ReadMarkers,
+        state::{ClientHandle, Control, State},
+    };
+    use std::{fs, path::Path, time::Instant};
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    // Reed is an operator, Sue a moderator and Ben a registered user
+    fn state(dir: &Path) -> State {
+        let mut config = Config::default();
+        for (nick, role) in [
+            ("Reed", Role::Operator),
+            ("Sue", Role::Moderator),
+            ("Ben", Role::User),
+        ] {
+            config.auth.users.push(UserConfig {
+                nick: nick.to_string(),
+                password_hash: String::new(),
+                role,
+            });
+        }
+        State::new(
+            ConfigSource::new(None, Default::default()),
+            config,
+            BanList::load(dir.join("bans.txt")).unwrap(),
+            MessageStore::load(dir.join("messages.jsonl"), 10).unwrap(),
+            ReadMarkers::load(dir.join("read_markers.json")).unwrap(),
+            Mailbox::load(dir.join("mailbox.jsonl"), 10).unwrap(),
+            ModerationLog::new(dir.join("moderation.log")),
+        )
+    }
+
+    // Connects `nick` from `addr`, returning what the server tells them
+    fn connect(
+        state: &State,
+        id: usize,
+        nick: &str,
+        addr: &str,
+    ) -> mpsc::UnboundedReceiver<Control> {
+        let (control, receiver) = mpsc::unbounded_channel();
+        let handle = ClientHandle {
+            addr: addr.parse().unwrap(),
+            nick: nick.to_string(),
+            room: Some(String::from("lobby")),
+            control,
+            last_active: Instant::now(),
+        };
+        state.register(id, handle);
+        receiver
+    }
+
+    fn actor(nick: &str) -> Actor<'_> {
+        Actor::joined(nick, Some("lobby")).unwrap()
+    }
+
+    #[test]
+    fn test_nick_ban_and_unban() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path());
+        let mut ben = connect(&state, 1, "Ben", "10.0.0.1:1000");
+        ban(&state, &actor("Sue"), "ben", "spam").unwrap();
+        assert!(matches!(ben.try_recv(), Ok(Control::Disconnect(_))));
+        {
+            let bans = state.bans.lock().unwrap();
+            assert!(bans.is_nick_banned("Ben"));
+            assert!(
+                !bans.is_addr_banned("10.0.0.1".parse().unwrap()),
+                "only the nick"
+            );
+        }
+
+        unban(&state, &actor("Sue"), "BEN").unwrap();
+        assert!(!state.bans.lock().unwrap().is_nick_banned("Ben"));
+        assert_eq!(fs::read_to_string(dir.path().join("bans.txt")).unwrap(), "");
+        assert!(matches!(
+            unban(&state, &actor("Sue"), "Ben"),
+            Err(ModerationError::NotBanned(_))
+        ));
+    }
+
+    #[test]
+    fn test_address_ban_needs_authority_over_everyone_there() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path());
+        let _reed = connect(&state, 1, "Reed", "10.0.0.1:1000");
+        let mut ben = connect(&state, 2, "Ben", "10.0.0.2:1000");
+        assert!(matches!(
+            ban(&state, &actor("Sue"), "10.0.0.1", ""),
+            Err(ModerationError::NotPermitted)
+        ));
+        assert!(matches!(
+            ban(&state, &actor("Sue"), "10.0.0.0/24", ""),
+            Err(ModerationError::NotPermitted)
+        ));
+        assert!(!state
+            .bans
+            .lock()
+            .unwrap()
+            .is_addr_banned("10.0.0.1".parse().unwrap()));
+
+        ban(&state, &actor("Sue"), "10.0.0.2", "").unwrap();
+        assert!(matches!(ben.try_recv(), Ok(Control::Disconnect(_))));
+        assert!(state
+            .bans
+            .lock()
+            .unwrap()
+            .is_addr_banned("10.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_wide_ranges_need_an_operator() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path());
+        for range in ["0.0.0.0/0", "10.0.0.0/8", "::/0", "2001:db8::/32"] {
+            assert!(matches!(
+                ban(&state, &actor("Sue"), range, ""),
+                Err(ModerationError::NotPermitted)
+            ));
+        }
+        ban(&state, &actor("Sue"), "10.1.0.0/16", "").unwrap();
+        ban(&state, &actor("Sue"), "2001:db8::/48", "").unwrap();
+        ban(&state, &actor("Reed"), "10.0.0.0/8", "").unwrap();
+        assert!(matches!(
+            ban(&state, &actor("Ben"), "10.2.3.4", ""),
+            Err(ModerationError::NotPermitted)
+        ));
+    }
+}
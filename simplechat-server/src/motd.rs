@@ -0,0 +1,118 @@
+/// Message of the day, filled in for each client as it joins
+///
+/// The template may refer to `{server}`, `{nick}`, `{room}`, `{online}` and
+/// `{uptime}`. Anything else in braces is left as it is.
+use crate::state::State;
+use std::time::Duration;
+use tracing::error;
+
+/// Values substituted into the template
+#[derive(Debug)]
+pub struct Vars<'a> {
+    pub server: &'a str,
+    pub nick: &'a str,
+    pub room: &'a str,
+    /// Clients currently joined to any room
+    pub online: usize,
+    pub uptime: Duration,
+}
+
+impl Vars<'_> {
+    fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "server" => Some(self.server.to_string()),
+            "nick" => Some(self.nick.to_string()),
+            "room" => Some(self.room.to_string()),
+            "online" => Some(self.online.to_string()),
+            "uptime" => Some(format_uptime(self.uptime)),
+            _ => None,
+        }
+    }
+}
+
+/// Builds the message of the day for `nick`, who just joined `room`
+pub fn for_client(state: &State, nick: &str, room: &str) -> Option<String> {
+    let config = state.config();
+    let template = match config.server.motd_template() {
+        Ok(template) => template?,
+        Err(e) => {
+            error!("failed to read message of the day: {:#}", e);
+            return None;
+        }
+    };
+    let online = state.find(|handle| handle.room.is_some()).len();
+    let vars = Vars {
+        server: &config.server.name,
+        nick,
+        room,
+        online,
+        uptime: state.stats.started.elapsed(),
+    };
+    Some(render(&template, &vars))
+}
+
+/// Replaces each known `{name}` in `template` with its value
+pub fn render(template: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, vars.get(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                out.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Formats a duration the way people say it, to the minute
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    if secs < 60 {
+        return format!("{}s", secs);
+    }
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    let parts: Vec<_> = [(days, "d"), (hours, "h"), (minutes, "m")]
+        .into_iter()
+        .skip_while(|(value, _)| *value == 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Vars};
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let vars = Vars {
+            server: "Baxter Building",
+            nick: "{uptime}",
+            room: "lab",
+            online: 4,
+            uptime: Duration::from_secs(2 * 86400 + 5 * 60 + 7),
+        };
+        assert_eq!(
+            render(
+                "Hi {nick}, welcome to {server}/{room}! {online} online",
+                &vars
+            ),
+            "Hi {uptime}, welcome to Baxter Building/lab! 4 online"
+        );
+        assert_eq!(render("up {uptime}", &vars), "up 2d 0h 5m");
+        assert_eq!(render("{unknown} {server", &vars), "{unknown} {server");
+    }
+}
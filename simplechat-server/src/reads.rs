@@ -0,0 +1,150 @@
+/// Persistent record of how far each user has read in each room
+use crate::state::RoomName;
+use anyhow::{Context, Result};
+use simplechat_protocol::{nick_key, MessageId};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::{error, info};
+
+/// The last message each nickname has seen in each room, kept in a JSON file
+/// that `flush` rewrites after someone has read further
+#[derive(Debug)]
+pub struct ReadMarkers {
+    path: PathBuf,
+    /// Positions by lowercased nickname, then room
+    positions: BTreeMap<String, BTreeMap<RoomName, MessageId>>,
+    /// Whether positions have moved since the file was last written
+    dirty: bool,
+}
+
+impl ReadMarkers {
+    /// Loads read positions from `path`; a missing file means nobody has read
+    /// anything yet
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let positions = read_positions(&path)
+            .with_context(|| format!("failed to load read positions {}", path.display()))?;
+        info!(path = %path.display(), users = positions.len(), "loaded read positions");
+        Ok(Self {
+            path,
+            positions,
+            dirty: false,
+        })
+    }
+
+    /// The last message `nick` has seen in `room`, if they have been there
+    pub fn get(&self, nick: &str, room: &str) -> Option<MessageId> {
+        self.positions.get(&nick_key(nick))?.get(room).copied()
+    }
+
+    /// Records that `nick` has seen message `id` in `room`, returning whether
+    /// that is further than they had read before. It is saved by the next
+    /// `flush`.
+    pub fn mark(&mut self, nick: &str, room: &str, id: MessageId) -> bool {
+        let rooms = self.positions.entry(nick_key(nick)).or_default();
+        match rooms.get(room) {
+            Some(&seen) if seen >= id => return false,
+            _ => rooms.insert(room.to_string(), id),
+        };
+        self.dirty = true;
+        true
+    }
+
+    /// Writes the positions out if they have moved since last time. The file
+    /// is written without holding the lock on `markers`.
+    pub fn flush(markers: &Mutex<Self>) {
+        let (path, contents) = {
+            let mut markers = markers.lock().unwrap();
+            if !markers.dirty {
+                return;
+            }
+            match serde_json::to_string_pretty(&markers.positions) {
+                Ok(contents) => {
+                    markers.dirty = false;
+                    (markers.path.clone(), contents)
+                }
+                Err(e) => {
+                    error!(path = %markers.path.display(), "failed to save read positions: {}", e);
+                    return;
+                }
+            }
+        };
+        if let Err(e) = write(&path, &contents) {
+            error!(path = %path.display(), "failed to save read positions: {:#}", e);
+            markers.lock().unwrap().dirty = true;
+        }
+    }
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    // Written beside the real file and renamed over it, so a crash can't
+    // leave it half written
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, contents)
+        .and_then(|_| fs::rename(&temp, path))
+        .with_context(|| format!("failed to write read positions {}", path.display()))
+}
+
+fn read_positions(path: &Path) -> Result<BTreeMap<String, BTreeMap<RoomName, MessageId>>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReadMarkers;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn markers(dir: &TempDir) -> ReadMarkers {
+        ReadMarkers::load(dir.path().join("read_markers.json")).unwrap()
+    }
+
+    #[test]
+    fn test_marks_only_move_forward() {
+        let dir = TempDir::new().unwrap();
+        let mut reads = markers(&dir);
+        assert_eq!(reads.get("Reed", "lab"), None);
+        assert!(reads.mark("Reed", "lab", 5));
+        assert!(!reads.mark("reed", "lab", 3), "never moves back");
+        assert!(!reads.mark("Reed", "lab", 5));
+        assert_eq!(reads.get("REED", "lab"), Some(5));
+        assert!(reads.mark("Reed", "lobby", 2));
+        assert_eq!(reads.get("Reed", "lobby"), Some(2));
+        assert_eq!(reads.get("Sue", "lab"), None);
+    }
+
+    #[test]
+    fn test_saved_only_when_flushed() {
+        let dir = TempDir::new().unwrap();
+        let reads = Mutex::new(markers(&dir));
+        reads.lock().unwrap().mark("Reed", "lab", 5);
+        assert_eq!(markers(&dir).get("Reed", "lab"), None);
+
+        ReadMarkers::flush(&reads);
+        assert_eq!(markers(&dir).get("reed", "lab"), Some(5));
+    }
+
+    #[test]
+    fn test_flush_skips_unchanged_positions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("read_markers.json");
+        let reads = Mutex::new(markers(&dir));
+        ReadMarkers::flush(&reads);
+        assert!(!path.exists(), "nothing to write yet");
+
+        reads.lock().unwrap().mark("Reed", "lab", 5);
+        ReadMarkers::flush(&reads);
+        std::fs::remove_file(&path).unwrap();
+        reads.lock().unwrap().mark("Reed", "lab", 4);
+        ReadMarkers::flush(&reads);
+        assert!(!path.exists(), "a mark that goes nowhere changes nothing");
+    }
+}
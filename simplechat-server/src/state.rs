@@ -0,0 +1,376 @@
+/// State shared between all client connections
+use crate::{
+    bans::BanList,
+    config::{Config, ConfigSource},
+    limits::ConnectionLimits,
+    log,
+    mailbox::Mailbox,
+    messages::MessageStore,
+    metrics::Stats,
+    moderation::{ModerationError, ModerationLog, Role},
+    reads::ReadMarkers,
+};
+use anyhow::Result;
+use simplechat_protocol::{nick_key, same_nick, ServerFrame};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::info;
+
+pub type ClientId = usize;
+pub type RoomName = String;
+
+/// Frame distributed to every client in `room`, except the sender if any,
+/// along with when it was relayed
+pub type RelayedMessage = (Option<ClientId>, RoomName, ServerFrame, Instant);
+
+/// Out-of-band instructions for a client task
+#[derive(Debug)]
+pub enum Control {
+    /// Close the connection, telling the client why
+    Disconnect(String),
+    /// Pass a frame on to the client, such as a private message
+    Deliver(ServerFrame),
+}
+
+/// What the server knows about a connected client
+#[derive(Clone, Debug)]
+pub struct ClientHandle {
+    pub addr: SocketAddr,
+    pub nick: String,
+    pub room: Option<RoomName>,
+    pub control: mpsc::UnboundedSender<Control>,
+    /// When the client last sent a frame
+    pub last_active: Instant,
+}
+
+#[derive(Debug)]
+pub struct State {
+    source: ConfigSource,
+    config: RwLock<Arc<Config>>,
+    pub limits: Arc<ConnectionLimits>,
+    pub bans: Mutex<BanList>,
+    pub messages: Mutex<MessageStore>,
+    pub reads: Mutex<ReadMarkers>,
+    pub mailbox: Mutex<Mailbox>,
+    pub modlog: ModerationLog,
+    pub relay_tx: broadcast::Sender<RelayedMessage>,
+    pub stats: Arc<Stats>,
+    clients: Mutex<HashMap<ClientId, ClientHandle>>,
+    /// Roles granted at runtime, which take precedence over the configuration
+    roles: Mutex<HashMap<String, Role>>,
+    mutes: Mutex<HashMap<String, Instant>>,
+    /// Whether each chat listener is accepting connections, by local address
+    listeners: Mutex<BTreeMap<SocketAddr, bool>>,
+}
+
+impl State {
+    /// Creates the server state from `config`, which was loaded from `source`
+    pub fn new(
+        source: ConfigSource,
+        config: Config,
+        bans: BanList,
+        messages: MessageStore,
+        reads: ReadMarkers,
+        mailbox: Mailbox,
+        modlog: ModerationLog,
+    ) -> Self {
+        let (relay_tx, _relay_rx) = broadcast::channel(config.limits.broadcast_capacity);
+        let limits = ConnectionLimits::new(
+            config.limits.max_connections,
+            config.limits.max_connections_per_ip,
+        );
+        Self {
+            source,
+            config: RwLock::new(Arc::new(config)),
+            limits,
+            bans: Mutex::new(bans),
+            messages: Mutex::new(messages),
+            reads: Mutex::new(reads),
+            mailbox: Mutex::new(mailbox),
+            modlog,
+            relay_tx,
+            stats: Arc::default(),
+            clients: Mutex::default(),
+            roles: Mutex::default(),
+            mutes: Mutex::default(),
+            listeners: Mutex::default(),
+        }
+    }
+
+    /// Records whether the chat listener on `addr` is accepting connections
+    pub fn set_accepting(&self, addr: SocketAddr, accepting: bool) {
+        self.listeners.lock().unwrap().insert(addr, accepting);
+    }
+
+    /// Every chat listener started so far and whether it is accepting
+    /// connections
+    pub fn listeners(&self) -> Vec<(SocketAddr, bool)> {
+        let listeners = self.listeners.lock().unwrap();
+        listeners.iter().map(|(&addr, &ok)| (addr, ok)).collect()
+    }
+
+    /// The configuration currently in effect
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Re-reads the configuration and applies it without disturbing
+    /// connected clients, returning the changed settings that only take effect
+    /// after a restart. Nothing is applied unless everything can be.
+    pub fn reload_config(&self) -> Result<Vec<&'static str>> {
+        let mut new = self.source.load()?;
+        let path = self.bans.lock().unwrap().path().to_path_buf();
+        let bans = BanList::load(path)?;
+        let old = self.config();
+        let restart_required = old.restart_required(&new);
+
+        // Keep reporting the settings actually in use until a restart
+        new.listen = old.listen.clone();
+        new.persistence = old.persistence.clone();
+        new.limits.broadcast_capacity = old.limits.broadcast_capacity;
+        new.logging.format = old.logging.format;
+
+        // The only step that can fail goes first
+        log::reconfigure(&new.logging)?;
+        *self.bans.lock().unwrap() = bans;
+        self.limits.set_maximums(
+            new.limits.max_connections,
+            new.limits.max_connections_per_ip,
+        );
+        let new = Arc::new(new);
+        *self.config.write().unwrap() = new.clone();
+        info!("reloaded configuration");
+        // Whoever holds a newly registered nickname never gave its password
+        let unproven = self.find(|handle| {
+            handle.room.is_some()
+                && old.user(&handle.nick).is_none()
+                && new.user(&handle.nick).is_some()
+        });
+        for handle in unproven {
+            let reason = String::from("that nickname is now registered, rejoin with its password");
+            let _ = handle.control.send(Control::Disconnect(reason));
+        }
+        if !restart_required.is_empty() {
+            info!(
+                settings = %restart_required.join(", "),
+                "some changes take effect after a restart"
+            );
+        }
+        Ok(restart_required)
+    }
+
+    /// Records a newly accepted client
+    pub fn register(&self, client_id: ClientId, handle: ClientHandle) {
+        self.clients.lock().unwrap().insert(client_id, handle);
+    }
+
+    /// Forgets a client whose connection has closed
+    pub fn unregister(&self, client_id: ClientId) {
+        self.clients.lock().unwrap().remove(&client_id);
+    }
+
+    /// Updates a client's nickname and room after it joins
+    pub fn set_joined(&self, client_id: ClientId, nick: &str, room: &str) {
+        if let Some(handle) = self.clients.lock().unwrap().get_mut(&client_id) {
+            handle.nick = nick.to_string();
+            handle.room = Some(room.to_string());
+        }
+    }
+
+    /// Notes that a client has just sent something
+    pub fn touch(&self, client_id: ClientId) {
+        if let Some(handle) = self.clients.lock().unwrap().get_mut(&client_id) {
+            handle.last_active = Instant::now();
+        }
+    }
+
+    /// Snapshot of every connected client, ordered by id
+    pub fn clients(&self) -> Vec<(ClientId, ClientHandle)> {
+        let mut clients: Vec<_> = self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, handle)| (*id, handle.clone()))
+            .collect();
+        clients.sort_by_key(|(id, _)| *id);
+        clients
+    }
+
+    /// Rooms that currently have at least one member
+    pub fn rooms(&self) -> BTreeSet<RoomName> {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|handle| handle.room.clone())
+            .collect()
+    }
+
+    /// Finds every connection matching `predicate`
+    pub fn find(&self, predicate: impl Fn(&ClientHandle) -> bool) -> Vec<ClientHandle> {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|handle| predicate(handle))
+            .cloned()
+            .collect()
+    }
+
+    /// Finds every joined connection currently using `nick`
+    pub fn find_by_nick(&self, nick: &str) -> Vec<ClientHandle> {
+        self.find(|handle| handle.room.is_some() && same_nick(&handle.nick, nick))
+    }
+
+    /// The nicknames of everyone in `room`
+    pub fn members(&self, room: &str) -> BTreeSet<String> {
+        self.find(|handle| handle.room.as_deref() == Some(room))
+            .into_iter()
+            .map(|handle| handle.nick)
+            .collect()
+    }
+
+    /// The role `nick` currently has. Only registered users have one, so it
+    /// applies once they have joined with their password.
+    pub fn role(&self, nick: &str) -> Role {
+        let config = self.config();
+        if config.user(nick).is_none() {
+            return Role::default();
+        }
+        let granted = self.roles.lock().unwrap().get(&nick_key(nick)).copied();
+        granted.unwrap_or_else(|| config.role(nick))
+    }
+
+    pub fn set_role(&self, nick: &str, role: Role) {
+        self.roles.lock().unwrap().insert(nick_key(nick), role);
+    }
+
+    /// Mutes `nick` for `duration`, or lifts the mute if `duration` is zero.
+    /// A mute too long to represent is refused without changing anything.
+    pub fn mute(&self, nick: &str, duration: Duration) -> Result<(), ModerationError> {
+        let until = Instant::now()
+            .checked_add(duration)
+            .ok_or(ModerationError::MuteTooLong)?;
+        let mut mutes = self.mutes.lock().unwrap();
+        if duration.is_zero() {
+            mutes.remove(&nick_key(nick));
+        } else {
+            mutes.insert(nick_key(nick), until);
+        }
+        Ok(())
+    }
+
+    /// Refuses anything `nick` would say while they are muted
+    pub fn check_muted(&self, nick: &str) -> Result<(), ModerationError> {
+        match self.muted_for(nick) {
+            Some(remaining) => Err(ModerationError::Muted(remaining)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns how much longer `nick` stays muted, if at all
+    fn muted_for(&self, nick: &str) -> Option<Duration> {
+        let mut mutes = self.mutes.lock().unwrap();
+        let key = nick_key(nick);
+        let remaining = mutes
+            .get(&key)?
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero());
+        if remaining.is_none() {
+            mutes.remove(&key);
+        }
+        remaining
+    }
+
+    /// Sends a server notice to everyone in `room`
+    pub fn announce(&self, room: &str, text: impl Into<String>) {
+        let frame = ServerFrame::notice(text);
+        // Nobody listening is not an error worth reporting for a notice
+        let _ = self
+            .relay_tx
+            .send((None, room.to_string(), frame, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::State;
+    use crate::{
+        bans::BanList,
+        config::{Config, ConfigSource},
+        mailbox::Mailbox,
+        messages::MessageStore,
+        moderation::{ModerationError, ModerationLog},
+        reads::ReadMarkers,
+    };
+    use std::{fs, path::Path, time::Duration};
+    use tempfile::TempDir;
+
+    fn state(dir: &Path, source: ConfigSource) -> State {
+        State::new(
+            source,
+            Config::default(),
+            BanList::load(dir.join("bans.txt")).unwrap(),
+            MessageStore::load(dir.join("messages.jsonl"), 10).unwrap(),
+            ReadMarkers::load(dir.join("read_markers.json")).unwrap(),
+            Mailbox::load(dir.join("mailbox.jsonl"), 10).unwrap(),
+            ModerationLog::new(dir.join("moderation.log")),
+        )
+    }
+
+    #[test]
+    fn test_mute_too_long() {
+        let dir = TempDir::new().unwrap();
+        let state = state(dir.path(), ConfigSource::new(None, Default::default()));
+        assert!(matches!(
+            state.mute("doom", Duration::from_secs(u64::MAX)),
+            Err(ModerationError::MuteTooLong)
+        ));
+        assert!(state.muted_for("doom").is_none());
+
+        state.mute("doom", Duration::from_secs(60)).unwrap();
+        assert!(state.muted_for("Doom").is_some());
+        let error = state.check_muted("DOOM").unwrap_err().to_string();
+        assert_eq!(error, "you are muted for another 60s");
+        state.mute("doom", Duration::ZERO).unwrap();
+        assert!(state.muted_for("doom").is_none());
+        assert!(state.check_muted("doom").is_ok());
+    }
+
+    #[test]
+    fn test_failed_reload_changes_nothing() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("server.toml");
+        fs::write(&file, "[limits]\nmax_connections = 5\n").unwrap();
+        let source = ConfigSource::new(Some(file), Default::default());
+        let state = state(dir.path(), source);
+        fs::write(dir.path().join("bans.txt"), "ip 10.0.0.1\n").unwrap();
+        state.reload_config().unwrap();
+        assert_eq!(state.config().limits.max_connections, 5);
+        assert!(state
+            .bans
+            .lock()
+            .unwrap()
+            .is_addr_banned([10, 0, 0, 1].into()));
+
+        fs::write(
+            dir.path().join("server.toml"),
+            "[limits]\nmax_connections = 6\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("bans.txt"), "host example.com\n").unwrap();
+        assert!(state.reload_config().is_err());
+        assert_eq!(state.config().limits.max_connections, 5);
+        assert!(state
+            .bans
+            .lock()
+            .unwrap()
+            .is_addr_banned([10, 0, 0, 1].into()));
+    }
+}